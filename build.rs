@@ -0,0 +1,12 @@
+use std::path::Path;
+
+/// axe-core is vendored by scripts/vendor-axe.sh rather than checked in. Bundle it when it's there.
+const AXE_PATH: &str = "vendor/axe-core/axe.min.js";
+
+fn main() {
+    println!("cargo:rustc-check-cfg=cfg(bundled_axe)");
+    println!("cargo:rerun-if-changed={}", AXE_PATH);
+    if Path::new(AXE_PATH).exists() {
+        println!("cargo:rustc-cfg=bundled_axe");
+    }
+}
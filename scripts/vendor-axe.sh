@@ -0,0 +1,15 @@
+#!/bin/sh
+# Downloads the pinned axe-core release into vendor/axe-core, where the build picks it up for --a11y.
+# axe-core is licensed under the MPL-2.0, its license is kept next to the script.
+set -eu
+
+AXE_VERSION="${AXE_VERSION:-4.10.2}"
+DESTINATION="$(dirname "$0")/../vendor/axe-core"
+TEMP="$(mktemp -d)"
+trap 'rm -rf "$TEMP"' EXIT
+
+curl -fsSL "https://registry.npmjs.org/axe-core/-/axe-core-$AXE_VERSION.tgz" | tar -xz -C "$TEMP"
+mkdir -p "$DESTINATION"
+cp "$TEMP/package/axe.min.js" "$TEMP/package/LICENSE" "$DESTINATION/"
+echo "$AXE_VERSION" > "$DESTINATION/VERSION"
+echo "Vendored axe-core $AXE_VERSION into $DESTINATION"
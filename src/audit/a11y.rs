@@ -0,0 +1,257 @@
+use crate::audit::rule::{Finding, Page, Rule, Severity};
+use crate::webdriver::session::is_session_dead;
+use crate::webscraper::find_urls::{Url, WebScrapingError};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+pub const A11Y_RULE: &str = "a11y";
+
+/// axe-core as vendored by scripts/vendor-axe.sh. Builds without it can still load a copy from `axe_path`.
+#[cfg(bundled_axe)]
+const BUNDLED_AXE: Option<&str> = Some(include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/vendor/axe-core/axe.min.js")));
+#[cfg(not(bundled_axe))]
+const BUNDLED_AXE: Option<&str> = None;
+
+/// Runs axe on the page and reports its violations. Appended to the axe source, which defines `window.axe`.
+const RUN_AXE_SCRIPT: &str = r#"
+    const [options, done] = arguments;
+    axe.run(document, options)
+        .then(results => done(results.violations.map(violation => ({
+            id: violation.id,
+            impact: violation.impact,
+            help: violation.help,
+            help_url: violation.helpUrl,
+            targets: violation.nodes.map(node => node.target.join(' ')),
+        }))))
+        .catch(error => done({ error: String(error) }));
+"#;
+
+/// The `[a11y]` section of the config file
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct A11yOptions {
+    /// Same as --a11y
+    pub enabled: bool,
+    /// axe.min.js to use instead of the bundled copy
+    pub axe_path: Option<String>,
+    /// Only run axe rules with these tags, like "wcag2a" or "wcag2aa"; every rule by default
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize)]
+pub struct Violation {
+    pub id: String,
+    /// minor, moderate, serious or critical
+    pub impact: Option<String>,
+    pub help: String,
+    pub help_url: String,
+    /// A CSS selector for every offending element
+    pub targets: Vec<String>,
+}
+
+impl Violation {
+    fn severity(&self) -> Severity {
+        match self.impact.as_deref() {
+            Some("critical") | Some("serious") => Severity::Error,
+            Some("minor") => Severity::Info,
+            _ => Severity::Warning,
+        }
+    }
+
+    /// A finding per offending element, named `a11y/<axe rule id>`
+    pub fn findings(&self) -> Vec<Finding> {
+        let rule = format!("{}/{}", A11Y_RULE, self.id);
+        self.targets
+            .iter()
+            .map(|target| {
+                let mut finding = Finding::new(&rule, self.severity(), self.help.clone()).at(target.clone());
+                finding.help_url = Some(self.help_url.clone());
+                finding
+            })
+            .collect()
+    }
+}
+
+/// Injects axe-core into every page with WebDriver `execute`, so it runs offline and isn't subject to the
+/// page's Content-Security-Policy
+pub struct AxeRule {
+    script: String,
+    run_options: Value,
+}
+
+impl AxeRule {
+    pub fn new(options: &A11yOptions) -> Result<AxeRule, WebScrapingError> {
+        let axe = match (&options.axe_path, BUNDLED_AXE) {
+            (Some(path), _) => fs::read_to_string(Path::new(path))
+                .map_err(|e| WebScrapingError::ConfigError(format!("could not read {}: {}", path, e)))?,
+            (None, Some(bundled)) => bundled.to_string(),
+            (None, None) => {
+                return Err(WebScrapingError::ConfigError(
+                    "this build does not bundle axe-core: run scripts/vendor-axe.sh and rebuild, or set axe_path in [a11y]"
+                        .to_string(),
+                ))
+            }
+        };
+        let run_options = if options.tags.is_empty() {
+            json!({})
+        } else {
+            json!({ "runOnly": { "type": "tag", "values": options.tags } })
+        };
+        Ok(AxeRule {
+            script: format!("{}\n{}", axe, RUN_AXE_SCRIPT),
+            run_options,
+        })
+    }
+}
+
+#[async_trait]
+impl Rule for AxeRule {
+    fn name(&self) -> &str {
+        A11Y_RULE
+    }
+
+    async fn check(&self, page: &mut Page<'_>) -> Result<Vec<Finding>, WebScrapingError> {
+        let value = match page.client.execute_async(&self.script, vec![self.run_options.clone()]).await {
+            Ok(value) => value,
+            Err(e) if is_session_dead(&e) => return Err(e.into()),
+            Err(e) => return Err(WebScrapingError::ScriptError(format!("axe-core failed: {}", e))),
+        };
+        if let Some(error) = value.get("error") {
+            return Err(WebScrapingError::ScriptError(format!("axe-core failed: {}", error)));
+        }
+        let violations: Vec<Violation> = serde_json::from_value(value)
+            .map_err(|e| WebScrapingError::ScriptError(format!("unexpected axe-core result: {}", e)))?;
+        Ok(violations.iter().flat_map(Violation::findings).collect())
+    }
+}
+
+/// One axe rule across the whole site
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct A11yRuleRollup {
+    pub id: String,
+    pub severity: Severity,
+    pub help: String,
+    pub help_url: Option<String>,
+    /// Offending elements on all pages together
+    pub elements: usize,
+    pub urls: Vec<String>,
+}
+
+/// The `accessibility` section of the report, worst and most widespread rules first
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct A11ySummary {
+    pub pages_with_violations: usize,
+    pub rules: Vec<A11yRuleRollup>,
+}
+
+pub fn a11y_rollup(index: &HashMap<String, Url>) -> A11ySummary {
+    let prefix = format!("{}/", A11Y_RULE);
+    let mut rules: BTreeMap<String, (A11yRuleRollup, BTreeSet<String>)> = BTreeMap::new();
+    let mut pages = 0;
+
+    for url in index.values() {
+        let findings: Vec<&Finding> = url.findings.iter().filter(|finding| finding.rule.starts_with(&prefix)).collect();
+        if !findings.is_empty() {
+            pages += 1;
+        }
+        for finding in findings {
+            let id = finding.rule[prefix.len()..].to_string();
+            let (rollup, urls) = rules.entry(id.clone()).or_insert_with(|| {
+                let rollup = A11yRuleRollup {
+                    id,
+                    severity: finding.severity,
+                    help: finding.message.clone(),
+                    help_url: finding.help_url.clone(),
+                    elements: 0,
+                    urls: Vec::new(),
+                };
+                (rollup, BTreeSet::new())
+            });
+            rollup.elements += 1;
+            urls.insert(url.full_path.clone());
+        }
+    }
+
+    let mut rules: Vec<A11yRuleRollup> = rules
+        .into_values()
+        .map(|(mut rollup, urls)| {
+            rollup.urls = urls.into_iter().collect();
+            rollup
+        })
+        .collect();
+    rules.sort_by(|a, b| b.severity.cmp(&a.severity).then(b.urls.len().cmp(&a.urls.len())));
+    A11ySummary {
+        pages_with_violations: pages,
+        rules,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn violation(id: &str, impact: &str, targets: &[&str]) -> Violation {
+        Violation {
+            id: id.to_string(),
+            impact: Some(impact.to_string()),
+            help: format!("{} help", id),
+            help_url: format!("https://dequeuniversity.com/rules/axe/4.10/{}", id),
+            targets: targets.iter().map(|target| target.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn violation_findings_test() {
+        let findings = violation("image-alt", "critical", &["img.hero", "#logo"]).findings();
+
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[0].rule, "a11y/image-alt");
+        assert_eq!(findings[0].severity, Severity::Error);
+        assert_eq!(findings[1].locator, Some("#logo".to_string()));
+        assert_eq!(
+            findings[1].help_url,
+            Some("https://dequeuniversity.com/rules/axe/4.10/image-alt".to_string())
+        );
+        assert_eq!(violation("region", "moderate", &["main"]).findings()[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn a11y_rollup_test() {
+        let mut home = Url::new("https://example.com/".to_string(), Some(200), "https://example.com/".to_string());
+        home.findings = violation("region", "moderate", &["header", "footer"]).findings();
+        let mut about = Url::new("https://example.com/about".to_string(), Some(200), "https://example.com/".to_string());
+        about.findings = violation("image-alt", "critical", &["img"]).findings();
+        about.findings.extend(violation("region", "moderate", &["aside"]).findings());
+        let clean = Url::new("https://example.com/clean".to_string(), Some(200), "https://example.com/".to_string());
+        let index = HashMap::from([
+            (home.full_path.clone(), home),
+            (about.full_path.clone(), about),
+            (clean.full_path.clone(), clean),
+        ]);
+
+        let summary = a11y_rollup(&index);
+
+        assert_eq!(summary.pages_with_violations, 2);
+        assert_eq!(summary.rules[0].id, "image-alt");
+        assert_eq!(summary.rules[1].id, "region");
+        assert_eq!(summary.rules[1].elements, 3);
+        assert_eq!(
+            summary.rules[1].urls,
+            vec!["https://example.com/".to_string(), "https://example.com/about".to_string()]
+        );
+    }
+
+    #[test]
+    fn axe_rule_source_test() {
+        let options = A11yOptions {
+            enabled: true,
+            axe_path: Some("does/not/exist/axe.min.js".to_string()),
+            tags: Vec::new(),
+        };
+        assert!(AxeRule::new(&options).is_err());
+    }
+}
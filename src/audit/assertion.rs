@@ -0,0 +1,155 @@
+use crate::audit::rule::{Finding, Page, Rule, Severity};
+use crate::webdriver::session::is_session_dead;
+use crate::webscraper::find_urls::WebScrapingError;
+use crate::webscraper::url_pattern::matches_pattern;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+/// An `[[assertions]]` entry from the config file
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct AssertionConfig {
+    pub pattern: String,
+    /// JavaScript file run as the body of a function in the page
+    pub script: String,
+    /// Name used on findings, defaults to the file name without extension
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AssertionResult {
+    pass: bool,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    locator: Option<String>,
+    #[serde(default)]
+    severity: Option<Severity>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AssertionOutcome {
+    Pass(bool),
+    One(AssertionResult),
+    Many(Vec<AssertionResult>),
+}
+
+/// JavaScript run in the browser on every page matching `pattern`. The script returns `true`/`false`,
+/// or one or a list of `{ pass, message, locator, severity }` results; every failed one is a finding.
+///
+/// ```js
+/// return [
+///     { pass: !!document.querySelector('.price'), message: 'Product page has no price' },
+///     { pass: !!document.querySelector('button.add-to-cart'), message: 'Product page has no add to cart button' },
+/// ];
+/// ```
+pub struct AssertionRule {
+    name: String,
+    pattern: String,
+    source: String,
+}
+
+impl AssertionRule {
+    pub fn load(config: &AssertionConfig) -> Result<AssertionRule, WebScrapingError> {
+        let source = fs::read_to_string(Path::new(&config.script))
+            .map_err(|e| WebScrapingError::ConfigError(format!("could not read {}: {}", config.script, e)))?;
+        let name = match &config.name {
+            Some(name) => name.clone(),
+            None => Path::new(&config.script)
+                .file_stem()
+                .map_or(config.script.clone(), |stem| stem.to_string_lossy().to_string()),
+        };
+        Ok(AssertionRule {
+            name,
+            pattern: config.pattern.clone(),
+            source,
+        })
+    }
+}
+
+#[async_trait]
+impl Rule for AssertionRule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn check(&self, page: &mut Page<'_>) -> Result<Vec<Finding>, WebScrapingError> {
+        if !matches_pattern(&self.pattern, &page.url.full_path) {
+            return Ok(Vec::new());
+        }
+        match page.client.execute(&self.source, Vec::new()).await {
+            Ok(value) => Ok(assertion_findings(&self.name, value)),
+            Err(e) if is_session_dead(&e) => Err(e.into()),
+            // The script threw, which usually means the element it relied on is missing
+            Err(e) => Ok(vec![Finding::new(
+                &self.name,
+                Severity::Error,
+                format!("Assertion script failed: {}", e),
+            )]),
+        }
+    }
+}
+
+/// Turns what the script returned into a finding per failed assertion
+fn assertion_findings(rule: &str, value: Value) -> Vec<Finding> {
+    let results = match serde_json::from_value(value) {
+        Ok(AssertionOutcome::Pass(true)) => Vec::new(),
+        Ok(AssertionOutcome::Pass(false)) => vec![AssertionResult {
+            pass: false,
+            message: None,
+            locator: None,
+            severity: None,
+        }],
+        Ok(AssertionOutcome::One(result)) => vec![result],
+        Ok(AssertionOutcome::Many(results)) => results,
+        Err(_) => {
+            return vec![Finding::new(
+                rule,
+                Severity::Error,
+                "Assertion script must return a boolean or { pass, message } results".to_string(),
+            )]
+        }
+    };
+
+    results
+        .into_iter()
+        .filter(|result| !result.pass)
+        .map(|result| Finding {
+            rule: rule.to_string(),
+            severity: result.severity.unwrap_or(Severity::Error),
+            message: result.message.unwrap_or_else(|| "Assertion failed".to_string()),
+            locator: result.locator,
+            help_url: None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn assertion_findings_test() {
+        assert_eq!(assertion_findings("product", json!(true)), Vec::new());
+        assert_eq!(
+            assertion_findings("product", json!(false)),
+            vec![Finding::new("product", Severity::Error, "Assertion failed".to_string())]
+        );
+        assert_eq!(
+            assertion_findings(
+                "product",
+                json!([
+                    { "pass": true, "message": "has a price" },
+                    { "pass": false, "message": "no add to cart button", "locator": "main", "severity": "warning" },
+                ])
+            ),
+            vec![Finding::new("product", Severity::Warning, "no add to cart button".to_string()).at("main".to_string())]
+        );
+        assert_eq!(assertion_findings("product", json!(null))[0].severity, Severity::Error);
+    }
+}
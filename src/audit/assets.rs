@@ -0,0 +1,192 @@
+use crate::audit::rule::{Finding, Page, Rule, RuleConfig, Severity};
+use crate::webscraper::find_urls::WebScrapingError;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+
+pub const BROKEN_ASSET_RULE: &str = "broken-asset";
+
+/// Defines `selectorOf(element)`, a CSS selector for exactly that element anchored at the nearest
+/// ancestor with an id, like `css_path` does for parsed html. Prepended to scripts that report elements.
+pub(crate) const SELECTOR_OF_SCRIPT: &str = r#"
+    const selectorOf = element => {
+        const parts = [];
+        for (let current = element; current && current.nodeType === 1; current = current.parentElement) {
+            if (current.id) {
+                parts.unshift('#' + CSS.escape(current.id));
+                break;
+            }
+            const name = current.localName;
+            if (name === 'html') {
+                parts.unshift(name);
+                break;
+            }
+            let position = 1;
+            for (let sibling = current.previousElementSibling; sibling; sibling = sibling.previousElementSibling) {
+                if (sibling.localName === name) position++;
+            }
+            parts.unshift(name + ':nth-of-type(' + position + ')');
+        }
+        return parts.join(' > ');
+    };
+"#;
+
+/// Collects images that rendered empty, stylesheets and scripts the Resource Timing API saw fail, and
+/// CSS background images that don't load. Backgrounds are loaded again with `Image`, since the browser
+/// doesn't expose whether they worked; each gets `timeout_ms` to do so.
+const BROKEN_ASSETS_SCRIPT: &str = r#"
+    const [timeoutMs, done] = arguments;
+    const broken = [];
+
+    for (const img of document.images) {
+        // Lazy images below the fold haven't been requested yet
+        if (img.currentSrc && img.complete && img.naturalWidth === 0) {
+            broken.push({ kind: 'image', url: img.currentSrc, selector: selectorOf(img) });
+        }
+    }
+
+    const failed = new Map();
+    for (const entry of performance.getEntriesByType('resource')) {
+        if (entry.responseStatus >= 400) failed.set(entry.name, entry.responseStatus);
+    }
+    for (const link of document.querySelectorAll('link[rel~="stylesheet"][href]')) {
+        // A stylesheet that failed to load has no sheet at all
+        if (failed.has(link.href) || (!link.disabled && link.sheet === null)) {
+            broken.push({ kind: 'stylesheet', url: link.href, selector: selectorOf(link), status: failed.get(link.href) });
+        }
+    }
+    for (const script of document.querySelectorAll('script[src]')) {
+        if (failed.has(script.src)) {
+            broken.push({ kind: 'script', url: script.src, selector: selectorOf(script), status: failed.get(script.src) });
+        }
+    }
+
+    const backgrounds = new Map();
+    for (const element of document.querySelectorAll('body, body *')) {
+        const image = getComputedStyle(element).backgroundImage;
+        for (const match of image.matchAll(/url\(["']?(.*?)["']?\)/g)) {
+            if (!match[1].startsWith('data:') && !backgrounds.has(match[1])) backgrounds.set(match[1], element);
+        }
+    }
+    const checks = [...backgrounds].map(([url, element]) => new Promise(resolve => {
+        const probe = new Image();
+        const timer = setTimeout(() => resolve(null), timeoutMs);
+        probe.onload = () => { clearTimeout(timer); resolve(null); };
+        probe.onerror = () => {
+            clearTimeout(timer);
+            resolve({ kind: 'background-image', url, selector: selectorOf(element), status: failed.get(url) });
+        };
+        probe.src = url;
+    }));
+    Promise.all(checks).then(results => done(broken.concat(results.filter(Boolean))));
+"#;
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct BrokenAssetOptions {
+    /// How long a CSS background image gets to load before it's given up on
+    timeout_ms: u64,
+}
+
+impl Default for BrokenAssetOptions {
+    fn default() -> Self {
+        BrokenAssetOptions { timeout_ms: 5000 }
+    }
+}
+
+/// An asset of the rendered page that failed to load
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize)]
+pub struct FailedAsset {
+    /// image, stylesheet, script or background-image
+    pub kind: String,
+    pub url: String,
+    /// The element that references the asset
+    pub selector: String,
+    /// Only known where the browser reports it in Resource Timing
+    #[serde(default)]
+    pub status: Option<u16>,
+}
+
+impl FailedAsset {
+    pub fn finding(&self) -> Finding {
+        let kind = match self.kind.as_str() {
+            "image" => "Image",
+            "stylesheet" => "Stylesheet",
+            "script" => "Script",
+            "background-image" => "Background image",
+            other => other,
+        };
+        let message = match self.status {
+            Some(status) => format!("{} {} responded with {}", kind, self.url, status),
+            None => format!("{} {} failed to load", kind, self.url),
+        };
+        Finding::new(BROKEN_ASSET_RULE, Severity::Error, message).at(self.selector.clone())
+    }
+}
+
+/// Images, stylesheets, scripts and CSS backgrounds that fail when the page renders, like a
+/// misconfigured CDN, which crawling the links alone doesn't notice
+pub struct BrokenAssets {
+    timeout_ms: u64,
+}
+
+impl BrokenAssets {
+    pub fn from_config(config: &RuleConfig) -> Result<Arc<dyn Rule>, WebScrapingError> {
+        let options: BrokenAssetOptions = config.options(BROKEN_ASSET_RULE)?;
+        Ok(Arc::new(BrokenAssets {
+            timeout_ms: options.timeout_ms,
+        }))
+    }
+}
+
+#[async_trait]
+impl Rule for BrokenAssets {
+    fn name(&self) -> &str {
+        BROKEN_ASSET_RULE
+    }
+
+    async fn check(&self, page: &mut Page<'_>) -> Result<Vec<Finding>, WebScrapingError> {
+        let value = page
+            .client
+            .execute_async(
+                &format!("{}{}", SELECTOR_OF_SCRIPT, BROKEN_ASSETS_SCRIPT),
+                vec![json!(self.timeout_ms)],
+            )
+            .await?;
+        let broken: Vec<FailedAsset> = serde_json::from_value(value).unwrap_or_default();
+        Ok(broken.iter().map(FailedAsset::finding).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn broken_asset_finding_test() {
+        let broken: Vec<FailedAsset> = serde_json::from_value(json!([
+            { "kind": "image", "url": "https://cdn.example.com/logo.png", "selector": "#header > img:nth-of-type(1)" },
+            { "kind": "stylesheet", "url": "https://cdn.example.com/site.css", "selector": "head > link:nth-of-type(2)", "status": 403 },
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            broken.iter().map(FailedAsset::finding).collect::<Vec<Finding>>(),
+            vec![
+                Finding::new(
+                    BROKEN_ASSET_RULE,
+                    Severity::Error,
+                    "Image https://cdn.example.com/logo.png failed to load".to_string()
+                )
+                .at("#header > img:nth-of-type(1)".to_string()),
+                Finding::new(
+                    BROKEN_ASSET_RULE,
+                    Severity::Error,
+                    "Stylesheet https://cdn.example.com/site.css responded with 403".to_string()
+                )
+                .at("head > link:nth-of-type(2)".to_string()),
+            ]
+        );
+    }
+}
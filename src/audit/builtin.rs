@@ -0,0 +1,158 @@
+use crate::audit::rule::{Finding, Page, Rule, RuleConfig, Severity};
+use crate::webscraper::find_urls::WebScrapingError;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Lists every id used by more than one element
+const DUPLICATE_ID_SCRIPT: &str = r#"
+    const counts = {};
+    for (const element of document.querySelectorAll('[id]')) {
+        if (element.id) counts[element.id] = (counts[element.id] || 0) + 1;
+    }
+    return Object.entries(counts)
+        .filter(([, count]) => count > 1)
+        .map(([id, count]) => ({ id, count, selector: '#' + CSS.escape(id) }));
+"#;
+
+/// Pages that answered with a 4xx or 5xx status
+pub struct HttpError;
+
+impl HttpError {
+    pub fn from_config(_config: &RuleConfig) -> Result<Arc<dyn Rule>, WebScrapingError> {
+        Ok(Arc::new(HttpError))
+    }
+}
+
+#[async_trait]
+impl Rule for HttpError {
+    fn name(&self) -> &str {
+        "http-error"
+    }
+
+    async fn check(&self, page: &mut Page<'_>) -> Result<Vec<Finding>, WebScrapingError> {
+        Ok(match page.url.response_code {
+            Some(code) if code >= 400 => vec![Finding::new(
+                self.name(),
+                Severity::Error,
+                format!("Page responded with {}", code),
+            )],
+            _ => Vec::new(),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct SlowPageOptions {
+    max_load_ms: u64,
+}
+
+impl Default for SlowPageOptions {
+    fn default() -> Self {
+        SlowPageOptions { max_load_ms: 3000 }
+    }
+}
+
+/// Pages whose load event fired later than `max_load_ms`
+pub struct SlowPage {
+    max_load_ms: u64,
+}
+
+impl SlowPage {
+    pub fn from_config(config: &RuleConfig) -> Result<Arc<dyn Rule>, WebScrapingError> {
+        let options: SlowPageOptions = config.options("slow-page")?;
+        Ok(Arc::new(SlowPage {
+            max_load_ms: options.max_load_ms,
+        }))
+    }
+}
+
+#[async_trait]
+impl Rule for SlowPage {
+    fn name(&self) -> &str {
+        "slow-page"
+    }
+
+    async fn check(&self, page: &mut Page<'_>) -> Result<Vec<Finding>, WebScrapingError> {
+        Ok(match &page.timings {
+            Some(timings) if timings.load_ms > self.max_load_ms as f64 => vec![Finding::new(
+                self.name(),
+                Severity::Warning,
+                format!("Page took {:.0}ms to load, the budget is {}ms", timings.load_ms, self.max_load_ms),
+            )],
+            _ => Vec::new(),
+        })
+    }
+}
+
+/// Pages kept out of search results by an `X-Robots-Tag: noindex` header, which is easy to miss
+/// because it isn't visible in the html
+pub struct XRobotsNoindex;
+
+impl XRobotsNoindex {
+    pub fn from_config(_config: &RuleConfig) -> Result<Arc<dyn Rule>, WebScrapingError> {
+        Ok(Arc::new(XRobotsNoindex))
+    }
+}
+
+#[async_trait]
+impl Rule for XRobotsNoindex {
+    fn name(&self) -> &str {
+        "x-robots-noindex"
+    }
+
+    fn needs_headers(&self) -> bool {
+        true
+    }
+
+    async fn check(&self, page: &mut Page<'_>) -> Result<Vec<Finding>, WebScrapingError> {
+        Ok(match page.headers.get("x-robots-tag") {
+            Some(value) if value.to_lowercase().contains("noindex") => vec![Finding::new(
+                self.name(),
+                Severity::Warning,
+                format!("X-Robots-Tag header '{}' keeps this page out of search results", value),
+            )],
+            _ => Vec::new(),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct DuplicateIdEntry {
+    id: String,
+    count: usize,
+    selector: String,
+}
+
+/// Element ids used more than once, which break fragment links and label associations
+pub struct DuplicateId;
+
+impl DuplicateId {
+    pub fn from_config(_config: &RuleConfig) -> Result<Arc<dyn Rule>, WebScrapingError> {
+        Ok(Arc::new(DuplicateId))
+    }
+}
+
+#[async_trait]
+impl Rule for DuplicateId {
+    fn name(&self) -> &str {
+        "duplicate-id"
+    }
+
+    async fn check(&self, page: &mut Page<'_>) -> Result<Vec<Finding>, WebScrapingError> {
+        let value = page.client.execute(DUPLICATE_ID_SCRIPT, Vec::new()).await?;
+        let duplicates: Vec<DuplicateIdEntry> = serde_json::from_value(value).unwrap_or_default();
+        Ok(duplicates
+            .into_iter()
+            .map(|duplicate| {
+                Finding::new(
+                    self.name(),
+                    Severity::Warning,
+                    format!("id '{}' is used by {} elements", duplicate.id, duplicate.count),
+                )
+                .at(duplicate.selector)
+            })
+            .collect())
+    }
+}
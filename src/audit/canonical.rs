@@ -0,0 +1,268 @@
+use crate::audit::rule::{Finding, Severity};
+use crate::audit::seo::find_crawled;
+use crate::webscraper::find_urls::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const HREFLANG_RULE: &str = "hreflang";
+pub const CANONICAL_CHAIN_RULE: &str = "canonical-chain";
+
+/// One problem with a page's hreflang alternates
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct HreflangIssue {
+    pub url: String,
+    /// The hreflang value the issue is about, if any
+    pub hreflang: Option<String>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// A canonical that points at a page which itself canonicalises somewhere else
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct CanonicalChain {
+    /// The page followed by every canonical target in order
+    pub urls: Vec<String>,
+    /// The chain leads back to a page already in it
+    pub is_loop: bool,
+}
+
+/// The `canonical` section of the report
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct CanonicalSummary {
+    pub pages_with_hreflang: usize,
+    pub hreflang_issues: Vec<HreflangIssue>,
+    pub canonical_chains: Vec<CanonicalChain>,
+}
+
+fn same_url(a: &str, b: &str) -> bool {
+    a.trim_end_matches('/') == b.trim_end_matches('/')
+}
+
+/// Checks the shape of a hreflang value: `x-default`, or an ISO 639 language with an optional
+/// script and ISO 3166 region, like `de`, `pt-BR` or `zh-Hant-TW`
+pub fn is_valid_hreflang(code: &str) -> bool {
+    if code.eq_ignore_ascii_case("x-default") {
+        return true;
+    }
+    let mut parts = code.split('-');
+    let language = parts.next().unwrap_or("");
+    if !(2..=3).contains(&language.len()) || !language.chars().all(|c| c.is_ascii_alphabetic()) {
+        return false;
+    }
+    let rest: Vec<&str> = parts.collect();
+    let (script, region) = match rest.as_slice() {
+        [] => (None, None),
+        [part] if part.len() == 4 => (Some(*part), None),
+        [part] => (None, Some(*part)),
+        [script, region] => (Some(*script), Some(*region)),
+        _ => return false,
+    };
+    let script_ok = script.is_none_or(|script| script.len() == 4 && script.chars().all(|c| c.is_ascii_alphabetic()));
+    // UK is the most common mistake, the ISO code for the United Kingdom is GB
+    let region_ok = region.is_none_or(|region| {
+        !region.eq_ignore_ascii_case("uk")
+            && ((region.len() == 2 && region.chars().all(|c| c.is_ascii_alphabetic()))
+                || (region.len() == 3 && region.chars().all(|c| c.is_ascii_digit())))
+    });
+    script_ok && region_ok
+}
+
+fn hreflang_issues(index: &HashMap<String, Url>) -> Vec<HreflangIssue> {
+    let mut issues = Vec::new();
+    let mut pages: Vec<&Url> = index
+        .values()
+        .filter(|url| url.response_code == Some(200) && !url.hreflang.is_empty())
+        .collect();
+    pages.sort_by(|a, b| a.full_path.cmp(&b.full_path));
+
+    for page in pages {
+        let mut issue = |hreflang: Option<&String>, severity, message: String| {
+            issues.push(HreflangIssue {
+                url: page.full_path.clone(),
+                hreflang: hreflang.cloned(),
+                severity,
+                message,
+            })
+        };
+
+        if !page.hreflang.keys().any(|code| code.eq_ignore_ascii_case("x-default")) {
+            issue(None, Severity::Warning, "No x-default hreflang alternate".to_string());
+        }
+        if !page.hreflang.values().any(|href| same_url(href, &page.full_path)) {
+            issue(None, Severity::Warning, "Hreflang alternates don't include the page itself".to_string());
+        }
+        for (code, href) in page.hreflang.iter() {
+            if !is_valid_hreflang(code) {
+                issue(Some(code), Severity::Error, format!("'{}' is not a valid hreflang value", code));
+            }
+            if same_url(href, &page.full_path) {
+                continue;
+            }
+            // Alternates outside the crawl can't be checked
+            let Some(target) = find_crawled(index, href) else {
+                continue;
+            };
+            if let Some(destination) = &target.redirected_to {
+                issue(Some(code), Severity::Error, format!("Alternate {} redirects to {}", href, destination));
+            } else if target.response_code != Some(200) {
+                let status = target.response_code.map_or("no response".to_string(), |code| code.to_string());
+                issue(Some(code), Severity::Error, format!("Alternate {} responded with {}", href, status));
+            } else if !target.is_canonical() {
+                issue(Some(code), Severity::Error, format!("Alternate {} is not self-canonical", href));
+            } else if !target.hreflang.values().any(|back| same_url(back, &page.full_path)) {
+                issue(Some(code), Severity::Error, format!("Alternate {} doesn't link back to this page", href));
+            }
+        }
+    }
+    issues
+}
+
+/// Follows canonicals from page to page. Only chains longer than one hop are returned.
+fn canonical_chains(index: &HashMap<String, Url>) -> Vec<CanonicalChain> {
+    let mut chains = Vec::new();
+    let mut pages: Vec<&Url> = index
+        .values()
+        .filter(|url| url.response_code == Some(200) && !url.is_canonical())
+        .collect();
+    pages.sort_by(|a, b| a.full_path.cmp(&b.full_path));
+
+    for page in pages {
+        let mut urls = vec![page.full_path.clone()];
+        let mut current = page;
+        let mut is_loop = false;
+        while let Some(canonical) = current.canonical.as_ref().filter(|_| !current.is_canonical()) {
+            if urls.iter().any(|seen| same_url(seen, canonical)) {
+                urls.push(canonical.clone());
+                is_loop = true;
+                break;
+            }
+            urls.push(canonical.clone());
+            match find_crawled(index, canonical) {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+        if is_loop || urls.len() > 2 {
+            chains.push(CanonicalChain { urls, is_loop });
+        }
+    }
+    chains
+}
+
+/// Validates hreflang alternates and canonical chains across the whole crawl and adds findings
+/// to the affected pages. Pass false to skip a check that was disabled in the config.
+pub fn analyze_canonicals(index: &mut HashMap<String, Url>, hreflang: bool, chains: bool) -> CanonicalSummary {
+    let summary = CanonicalSummary {
+        pages_with_hreflang: index.values().filter(|url| !url.hreflang.is_empty()).count(),
+        hreflang_issues: if hreflang { hreflang_issues(index) } else { Vec::new() },
+        canonical_chains: if chains { canonical_chains(index) } else { Vec::new() },
+    };
+
+    for issue in summary.hreflang_issues.iter() {
+        if let Some(page) = index.get_mut(&issue.url) {
+            page.findings
+                .push(Finding::new(HREFLANG_RULE, issue.severity, issue.message.clone()));
+        }
+    }
+    for chain in summary.canonical_chains.iter() {
+        if let Some(page) = index.get_mut(&chain.urls[0]) {
+            let (severity, kind) = if chain.is_loop {
+                (Severity::Error, "loop")
+            } else {
+                (Severity::Warning, "chain")
+            };
+            page.findings.push(Finding::new(
+                CANONICAL_CHAIN_RULE,
+                severity,
+                format!("Canonical {}: {}", kind, chain.urls.join(" -> ")),
+            ));
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn page(path: &str, alternates: &[(&str, &str)]) -> (String, Url) {
+        let url = Url {
+            hreflang: alternates
+                .iter()
+                .map(|(code, href)| (code.to_string(), href.to_string()))
+                .collect::<BTreeMap<String, String>>(),
+            ..Url::new(path.to_string(), Some(200), "https://example.com/".to_string())
+        };
+        (path.to_string(), url)
+    }
+
+    #[test]
+    fn is_valid_hreflang_test() {
+        for valid in ["en", "de-AT", "pt-br", "zh-Hant-TW", "es-419", "x-default"] {
+            assert!(is_valid_hreflang(valid), "{}", valid);
+        }
+        for invalid in ["en-UK", "english", "e", "en_US", "en-US-x", "de-"] {
+            assert!(!is_valid_hreflang(invalid), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn hreflang_issues_test() {
+        let en = "https://example.com/en/";
+        let de = "https://example.com/de/";
+        let fr = "https://example.com/fr/";
+        let index = HashMap::from([
+            page(en, &[("en", en), ("de", de), ("fr", fr), ("x-default", en)]),
+            page(de, &[("en", en), ("de", de), ("x-default", en)]),
+            page(fr, &[("fr", fr), ("en-UK", en), ("de", de)]),
+        ]);
+
+        let messages: Vec<(String, String)> = hreflang_issues(&index)
+            .into_iter()
+            .map(|issue| (issue.url, issue.message))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (fr.to_string(), "No x-default hreflang alternate".to_string()),
+                (fr.to_string(), format!("Alternate {} doesn't link back to this page", de)),
+                (fr.to_string(), "'en-UK' is not a valid hreflang value".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn canonical_chains_test() {
+        let mut index = HashMap::from([
+            page("https://example.com/a", &[]),
+            page("https://example.com/b", &[]),
+            page("https://example.com/c", &[]),
+            page("https://example.com/x", &[]),
+            page("https://example.com/y", &[]),
+        ]);
+        let canonicals = [("a", "b"), ("b", "c"), ("x", "y"), ("y", "x")];
+        for (from, to) in canonicals {
+            index.get_mut(&format!("https://example.com/{}", from)).unwrap().canonical =
+                Some(format!("https://example.com/{}", to));
+        }
+
+        let summary = analyze_canonicals(&mut index, true, true);
+
+        assert_eq!(summary.canonical_chains.len(), 3);
+        assert_eq!(
+            summary.canonical_chains[0],
+            CanonicalChain {
+                urls: vec![
+                    "https://example.com/a".to_string(),
+                    "https://example.com/b".to_string(),
+                    "https://example.com/c".to_string()
+                ],
+                is_loop: false,
+            }
+        );
+        assert!(summary.canonical_chains[1].is_loop);
+        assert_eq!(index["https://example.com/x"].findings[0].severity, Severity::Error);
+        assert!(index["https://example.com/b"].findings.is_empty());
+    }
+}
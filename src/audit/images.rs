@@ -0,0 +1,220 @@
+use crate::audit::assets::SELECTOR_OF_SCRIPT;
+use crate::audit::rule::{Finding, Page, Rule, RuleConfig, Severity};
+use crate::webscraper::find_urls::WebScrapingError;
+use async_trait::async_trait;
+use reqwest::Url as ParsedUrl;
+use serde::Deserialize;
+use std::sync::Arc;
+
+pub const IMAGE_RULE: &str = "image-optimization";
+
+/// Formats that have a smaller modern replacement in WebP or AVIF
+const LEGACY_FORMATS: &[&str] = &["jpg", "jpeg", "png", "gif", "bmp"];
+
+/// Describes every loaded `<img>`: its intrinsic and rendered size, the attributes that matter
+/// for loading, whether it sits below the first screen, and its size from Resource Timing
+const IMAGES_SCRIPT: &str = r#"
+    const sizes = new Map();
+    for (const entry of performance.getEntriesByType('resource')) {
+        sizes.set(entry.name, entry.encodedBodySize || entry.transferSize || 0);
+    }
+    return [...document.images]
+        .filter(img => img.currentSrc && img.complete && img.naturalWidth > 0)
+        .map(img => {
+            const rect = img.getBoundingClientRect();
+            return {
+                url: img.currentSrc,
+                selector: selectorOf(img),
+                natural_width: img.naturalWidth,
+                natural_height: img.naturalHeight,
+                rendered_width: rect.width,
+                rendered_height: rect.height,
+                has_dimensions: img.hasAttribute('width') && img.hasAttribute('height'),
+                lazy: img.loading === 'lazy',
+                below_fold: rect.top + window.scrollY > window.innerHeight,
+                alt: img.getAttribute('alt'),
+                bytes: sizes.get(img.currentSrc) || null,
+            };
+        });
+"#;
+
+/// `[rules.image-optimization]` options
+#[derive(Deserialize)]
+#[serde(default)]
+struct ImageOptions {
+    /// How much larger than rendered an image may be; 2 leaves room for high density screens
+    max_oversize: f64,
+    /// JPEG, PNG and GIF files from this many bytes should be WebP or AVIF
+    modern_format_min_bytes: u64,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        ImageOptions {
+            max_oversize: 2.0,
+            modern_format_min_bytes: 100_000,
+        }
+    }
+}
+
+/// An `<img>` as the page rendered it
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+pub struct RenderedImage {
+    pub url: String,
+    pub selector: String,
+    pub natural_width: f64,
+    pub natural_height: f64,
+    pub rendered_width: f64,
+    pub rendered_height: f64,
+    /// Both `width` and `height` attributes are set, so the browser can reserve the space
+    pub has_dimensions: bool,
+    pub lazy: bool,
+    pub below_fold: bool,
+    pub alt: Option<String>,
+    /// Unknown for cross-origin images without `Timing-Allow-Origin`
+    pub bytes: Option<u64>,
+}
+
+impl RenderedImage {
+    fn extension(&self) -> Option<String> {
+        let path = ParsedUrl::parse(&self.url).ok()?.path().to_string();
+        let (_, extension) = path.rsplit_once('.')?;
+        Some(extension.to_lowercase())
+    }
+}
+
+/// Images served much larger than they are shown, without dimensions, loaded eagerly below the
+/// fold, in a legacy format while large, or without alt text
+pub struct ImageOptimization {
+    options: ImageOptions,
+}
+
+impl ImageOptimization {
+    pub fn from_config(config: &RuleConfig) -> Result<Arc<dyn Rule>, WebScrapingError> {
+        Ok(Arc::new(ImageOptimization {
+            options: config.options(IMAGE_RULE)?,
+        }))
+    }
+
+    fn findings(&self, image: &RenderedImage) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        let mut flag = |severity, message: String| {
+            findings.push(Finding::new(IMAGE_RULE, severity, message).at(image.selector.clone()))
+        };
+        let is_svg = image.extension().as_deref() == Some("svg");
+
+        // Hidden images have no rendered size to compare with
+        if !is_svg
+            && image.rendered_width > 0.0
+            && image.natural_width > image.rendered_width * self.options.max_oversize
+        {
+            flag(
+                Severity::Warning,
+                format!(
+                    "Image {} is {}x{} but shown at {:.0}x{:.0}",
+                    image.url, image.natural_width, image.natural_height, image.rendered_width, image.rendered_height
+                ),
+            );
+        }
+        if !image.has_dimensions {
+            flag(
+                Severity::Warning,
+                format!("Image {} has no width and height attributes, which causes layout shifts", image.url),
+            );
+        }
+        if image.below_fold && !image.lazy {
+            flag(
+                Severity::Info,
+                format!("Image {} is below the fold but not loading=\"lazy\"", image.url),
+            );
+        }
+        let is_legacy = image
+            .extension()
+            .is_some_and(|extension| LEGACY_FORMATS.contains(&extension.as_str()));
+        if let Some(bytes) = image.bytes.filter(|bytes| is_legacy && *bytes >= self.options.modern_format_min_bytes) {
+            flag(
+                Severity::Info,
+                format!("Image {} is {} KB, WebP or AVIF would be smaller", image.url, bytes / 1024),
+            );
+        }
+        // alt="" marks a decorative image and is fine
+        if image.alt.is_none() {
+            flag(Severity::Warning, format!("Image {} has no alt attribute", image.url));
+        }
+        findings
+    }
+}
+
+#[async_trait]
+impl Rule for ImageOptimization {
+    fn name(&self) -> &str {
+        IMAGE_RULE
+    }
+
+    async fn check(&self, page: &mut Page<'_>) -> Result<Vec<Finding>, WebScrapingError> {
+        let value = page
+            .client
+            .execute(&format!("{}{}", SELECTOR_OF_SCRIPT, IMAGES_SCRIPT), Vec::new())
+            .await?;
+        let images: Vec<RenderedImage> = serde_json::from_value(value).unwrap_or_default();
+        Ok(images.iter().flat_map(|image| self.findings(image)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hero() -> RenderedImage {
+        RenderedImage {
+            url: "https://example.com/img/hero.jpg?w=4000".to_string(),
+            selector: "#hero > img:nth-of-type(1)".to_string(),
+            natural_width: 4000.0,
+            natural_height: 2000.0,
+            rendered_width: 1200.0,
+            rendered_height: 600.0,
+            has_dimensions: false,
+            lazy: false,
+            below_fold: false,
+            alt: None,
+            bytes: Some(2_400_000),
+        }
+    }
+
+    #[test]
+    fn image_findings_test() {
+        let rule = ImageOptimization {
+            options: ImageOptions::default(),
+        };
+
+        let messages: Vec<String> = rule.findings(&hero()).into_iter().map(|finding| finding.message).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Image https://example.com/img/hero.jpg?w=4000 is 4000x2000 but shown at 1200x600",
+                "Image https://example.com/img/hero.jpg?w=4000 has no width and height attributes, which causes layout shifts",
+                "Image https://example.com/img/hero.jpg?w=4000 is 2343 KB, WebP or AVIF would be smaller",
+                "Image https://example.com/img/hero.jpg?w=4000 has no alt attribute",
+            ]
+        );
+        assert_eq!(rule.findings(&hero())[0].locator, Some("#hero > img:nth-of-type(1)".to_string()));
+
+        let optimized = RenderedImage {
+            url: "https://example.com/img/teaser.avif".to_string(),
+            natural_width: 800.0,
+            natural_height: 400.0,
+            rendered_width: 400.0,
+            rendered_height: 200.0,
+            has_dimensions: true,
+            below_fold: true,
+            lazy: true,
+            alt: Some(String::new()),
+            ..hero()
+        };
+        assert!(rule.findings(&optimized).is_empty());
+        assert_eq!(
+            rule.findings(&RenderedImage { lazy: false, ..optimized })[0].severity,
+            Severity::Info
+        );
+    }
+}
@@ -0,0 +1,371 @@
+use crate::report::{update_report, Report};
+use crate::webscraper::find_urls::{read_from_file, write_to_file, Url, WebScrapingError};
+use crate::webscraper::resource::ResourceKind;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// Audits that are only shown for information and have no pass or fail
+const UNSCORED_MODES: &[&str] = &["informative", "manual", "notApplicable", "error"];
+
+/// The score Lighthouse itself shows as passed
+const PASSING_SCORE: f64 = 0.9;
+
+/// The `[lighthouse]` section of the config file
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LighthouseOptions {
+    /// Program and leading arguments, split on whitespace, e.g. `npx lighthouse`. The url is
+    /// passed after them, followed by `args`.
+    pub command: String,
+    pub args: Vec<String>,
+    /// Lighthouse runs at the same time
+    pub concurrency: usize,
+    /// A run taking longer than this is killed
+    pub timeout_secs: u64,
+}
+
+impl Default for LighthouseOptions {
+    fn default() -> Self {
+        LighthouseOptions {
+            command: "lighthouse".to_string(),
+            args: vec![
+                "--output=json".to_string(),
+                "--output-path=stdout".to_string(),
+                "--quiet".to_string(),
+                "--chrome-flags=--headless".to_string(),
+            ],
+            concurrency: 2,
+            timeout_secs: 120,
+        }
+    }
+}
+
+/// An audit that scored below 0.9
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct FailedAudit {
+    pub id: String,
+    pub title: String,
+    pub score: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_value: Option<String>,
+}
+
+/// What Lighthouse reported for a url
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct LighthouseResult {
+    /// Category id (performance, accessibility, ...) to score from 0 to 100
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub scores: BTreeMap<String, f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failed_audits: Vec<FailedAudit>,
+    /// Why the run produced no report
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl LighthouseResult {
+    fn failed(error: String) -> LighthouseResult {
+        LighthouseResult {
+            error: Some(error),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct LighthouseCategory {
+    score: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LighthouseAudit {
+    title: String,
+    score: Option<f64>,
+    #[serde(default)]
+    score_display_mode: Option<String>,
+    #[serde(default)]
+    display_value: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LighthouseReport {
+    #[serde(default)]
+    categories: BTreeMap<String, LighthouseCategory>,
+    #[serde(default)]
+    audits: BTreeMap<String, LighthouseAudit>,
+    #[serde(default)]
+    runtime_error: Option<Value>,
+}
+
+/// Reads the scores and failed audits out of a Lighthouse JSON report
+pub fn parse_lighthouse_report(json: &str) -> LighthouseResult {
+    let report: LighthouseReport = match serde_json::from_str(json) {
+        Ok(report) => report,
+        Err(e) => return LighthouseResult::failed(format!("could not parse Lighthouse output: {}", e)),
+    };
+    if let Some(error) = report.runtime_error {
+        let message = error.get("message").and_then(Value::as_str).map(String::from);
+        return LighthouseResult::failed(message.unwrap_or_else(|| error.to_string()));
+    }
+
+    let scores = report
+        .categories
+        .into_iter()
+        .filter_map(|(id, category)| Some((id, (category.score? * 100.0).round())))
+        .collect();
+    let mut failed_audits: Vec<FailedAudit> = report
+        .audits
+        .into_iter()
+        .filter(|(_, audit)| {
+            audit
+                .score_display_mode
+                .as_deref()
+                .is_none_or(|mode| !UNSCORED_MODES.contains(&mode))
+        })
+        .filter_map(|(id, audit)| {
+            let score = audit.score.filter(|score| *score < PASSING_SCORE)?;
+            Some(FailedAudit {
+                id,
+                title: audit.title,
+                score,
+                display_value: audit.display_value,
+            })
+        })
+        .collect();
+    failed_audits.sort_by(|a, b| a.score.total_cmp(&b.score).then(a.id.cmp(&b.id)));
+    LighthouseResult {
+        scores,
+        failed_audits,
+        error: None,
+    }
+}
+
+/// Runs the configured command for a single url and parses what it prints
+pub async fn run_lighthouse(url: &str, options: &LighthouseOptions) -> LighthouseResult {
+    let mut words = options.command.split_whitespace();
+    let Some(program) = words.next() else {
+        return LighthouseResult::failed("no lighthouse command configured".to_string());
+    };
+    let mut command = Command::new(program);
+    command
+        .args(words)
+        .arg(url)
+        .args(&options.args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let output = match tokio::time::timeout(Duration::from_secs(options.timeout_secs), command.output()).await {
+        Err(_) => return LighthouseResult::failed(format!("timed out after {}s", options.timeout_secs)),
+        Ok(Err(e)) => return LighthouseResult::failed(format!("could not run {}: {}", program, e)),
+        Ok(Ok(output)) => output,
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr.lines().last().unwrap_or_default().trim();
+        return LighthouseResult::failed(format!("{} exited with {}: {}", program, output.status, reason));
+    }
+    parse_lighthouse_report(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// How often an audit failed across the site
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct AuditCount {
+    pub id: String,
+    pub title: String,
+    pub pages: usize,
+}
+
+/// The `lighthouse` section of the report
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct LighthouseSummary {
+    pub pages_audited: usize,
+    /// Urls Lighthouse could not produce a report for
+    pub errors: Vec<String>,
+    /// Mean score per category
+    pub average_scores: BTreeMap<String, f64>,
+    /// Most often failed audits first
+    pub failed_audits: Vec<AuditCount>,
+}
+
+pub fn lighthouse_summary(index: &HashMap<String, Url>) -> LighthouseSummary {
+    let mut summary = LighthouseSummary::default();
+    let mut totals: BTreeMap<String, (f64, usize)> = BTreeMap::new();
+    let mut audits: BTreeMap<String, AuditCount> = BTreeMap::new();
+
+    for url in index.values() {
+        let Some(result) = &url.lighthouse else {
+            continue;
+        };
+        summary.pages_audited += 1;
+        if result.error.is_some() {
+            summary.errors.push(url.full_path.clone());
+        }
+        for (category, score) in result.scores.iter() {
+            let total = totals.entry(category.clone()).or_default();
+            total.0 += score;
+            total.1 += 1;
+        }
+        for audit in result.failed_audits.iter() {
+            audits
+                .entry(audit.id.clone())
+                .or_insert_with(|| AuditCount {
+                    id: audit.id.clone(),
+                    title: audit.title.clone(),
+                    pages: 0,
+                })
+                .pages += 1;
+        }
+    }
+
+    summary.errors.sort();
+    summary.average_scores = totals
+        .into_iter()
+        .map(|(category, (total, count))| (category, (total / count as f64).round()))
+        .collect();
+    summary.failed_audits = audits.into_values().collect();
+    summary.failed_audits.sort_by(|a, b| b.pages.cmp(&a.pages).then(a.id.cmp(&b.id)));
+    summary
+}
+
+/// Pages worth a Lighthouse run: web pages that loaded successfully
+fn is_auditable(url: &Url) -> bool {
+    url.response_code == Some(200) && url.resource_kind.is_none_or(|kind| kind == ResourceKind::Html)
+}
+
+/// Runs Lighthouse on every 200 page of a crawl result, `concurrency` at a time, and stores the
+/// scores and failed audits on each url
+pub async fn audit_lighthouse(input_path: &str, options: &LighthouseOptions) -> Result<(), WebScrapingError> {
+    let mut index = read_from_file(input_path)?;
+    let mut urls: Vec<String> = index
+        .values()
+        .filter(|url| is_auditable(url))
+        .map(|url| url.full_path.clone())
+        .collect();
+    urls.sort();
+    println!("Running Lighthouse on {} pages", urls.len());
+
+    let mut results = stream::iter(urls)
+        .map(|url| async move {
+            let result = run_lighthouse(&url, options).await;
+            (url, result)
+        })
+        .buffer_unordered(options.concurrency.max(1));
+    while let Some((url, result)) = results.next().await {
+        match &result.error {
+            Some(error) => println!("Lighthouse failed for {}: {}", url, error),
+            None => println!("Lighthouse {} {:?}", url, result.scores),
+        }
+        if let Some(url_object) = index.get_mut(&url) {
+            url_object.lighthouse = Some(result);
+        }
+    }
+
+    let summary = lighthouse_summary(&index);
+    write_to_file(index, input_path)?;
+    update_report(|report: &mut Report| report.lighthouse = Some(summary))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const REPORT: &str = r#"{
+        "lighthouseVersion": "12.2.1",
+        "categories": {
+            "performance": { "score": 0.73 },
+            "accessibility": { "score": 1 },
+            "seo": { "score": null }
+        },
+        "audits": {
+            "largest-contentful-paint": { "title": "Largest Contentful Paint", "score": 0.41, "scoreDisplayMode": "numeric", "displayValue": "3.9 s" },
+            "image-alt": { "title": "Image elements have [alt] attributes", "score": 0, "scoreDisplayMode": "binary" },
+            "document-title": { "title": "Document has a <title> element", "score": 1, "scoreDisplayMode": "binary" },
+            "diagnostics": { "title": "Diagnostics", "score": null, "scoreDisplayMode": "informative" },
+            "font-size": { "title": "Document uses legible font sizes", "score": 0, "scoreDisplayMode": "notApplicable" }
+        }
+    }"#;
+
+    /// Writes a shell script that prints `output`, standing in for the lighthouse binary
+    fn stub_command(name: &str, script: &str) -> LighthouseOptions {
+        let path = std::env::temp_dir().join(format!("web_audit_lighthouse_{}_{}.sh", name, std::process::id()));
+        fs::write(&path, script).unwrap();
+        LighthouseOptions {
+            command: format!("sh {}", path.display()),
+            args: Vec::new(),
+            concurrency: 1,
+            timeout_secs: 1,
+        }
+    }
+
+    #[test]
+    fn parse_lighthouse_report_test() {
+        let result = parse_lighthouse_report(REPORT);
+
+        assert_eq!(
+            result.scores,
+            BTreeMap::from([("accessibility".to_string(), 100.0), ("performance".to_string(), 73.0)])
+        );
+        assert_eq!(
+            result.failed_audits.iter().map(|audit| audit.id.as_str()).collect::<Vec<&str>>(),
+            vec!["image-alt", "largest-contentful-paint"]
+        );
+        assert_eq!(result.failed_audits[1].display_value, Some("3.9 s".to_string()));
+        assert_eq!(result.error, None);
+
+        let failed = parse_lighthouse_report(r#"{ "runtimeError": { "code": "NO_FCP", "message": "The page did not paint" } }"#);
+        assert_eq!(failed.error, Some("The page did not paint".to_string()));
+        assert!(parse_lighthouse_report("not json").error.is_some());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn run_lighthouse_stub_test() {
+        let stub = stub_command("ok", &format!("cat <<'EOF'\n{}\nEOF\n", REPORT));
+        let result = run_lighthouse("https://example.com/", &stub).await;
+        assert_eq!(result.scores["performance"], 73.0);
+
+        let failing = stub_command("exit", "echo 'Chrome could not be found' >&2\nexit 1\n");
+        assert!(run_lighthouse("https://example.com/", &failing)
+            .await
+            .error
+            .unwrap()
+            .ends_with("Chrome could not be found"));
+
+        let slow = stub_command("slow", "sleep 5\n");
+        assert_eq!(
+            run_lighthouse("https://example.com/", &slow).await.error,
+            Some("timed out after 1s".to_string())
+        );
+    }
+
+    #[test]
+    fn lighthouse_summary_test() {
+        let mut index = HashMap::new();
+        for (path, result) in [
+            ("https://example.com/a", parse_lighthouse_report(REPORT)),
+            ("https://example.com/b", parse_lighthouse_report(&REPORT.replace("0.73", "0.91"))),
+            ("https://example.com/c", LighthouseResult::failed("timed out after 1s".to_string())),
+        ] {
+            let mut url = Url::new(path.to_string(), Some(200), "https://example.com/".to_string());
+            url.lighthouse = Some(result);
+            index.insert(path.to_string(), url);
+        }
+
+        let summary = lighthouse_summary(&index);
+
+        assert_eq!(summary.pages_audited, 3);
+        assert_eq!(summary.errors, vec!["https://example.com/c".to_string()]);
+        assert_eq!(summary.average_scores["performance"], 82.0);
+        assert_eq!(summary.failed_audits[0].pages, 2);
+    }
+}
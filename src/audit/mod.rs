@@ -0,0 +1,13 @@
+pub mod a11y;
+pub mod assertion;
+pub mod assets;
+pub mod builtin;
+pub mod canonical;
+pub mod images;
+pub mod lighthouse;
+pub mod performance;
+pub mod rule;
+pub mod script;
+pub mod security;
+pub mod seo;
+pub mod weight;
@@ -0,0 +1,413 @@
+use crate::audit::rule::{Finding, Page, Rule, Severity};
+use crate::audit::weight::{AssetType, PageResources};
+use crate::webscraper::find_urls::{Url, WebScrapingError};
+use crate::webscraper::url_pattern::matches_pattern;
+use async_trait::async_trait;
+use fantoccini::error::CmdError;
+use fantoccini::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const BUDGET_RULE: &str = "performance-budget";
+
+/// Observes largest contentful paint, layout shifts and long tasks into `window.__webAuditPerformance`.
+/// `buffered` replays what happened before the observers were installed, where the browser supports it.
+const INSTALL_PERFORMANCE_SCRIPT: &str = r#"
+    if (window.__webAuditPerformance) return;
+    const state = window.__webAuditPerformance = { lcp: null, cls: null, longTasks: [] };
+    const supported = PerformanceObserver.supportedEntryTypes || [];
+    const observe = (type, callback) => {
+        if (!supported.includes(type)) return false;
+        new PerformanceObserver(list => list.getEntries().forEach(callback)).observe({ type, buffered: true });
+        return true;
+    };
+    observe('largest-contentful-paint', entry => { state.lcp = entry.startTime; });
+    // CLS is the worst session window: shifts less than 1s apart, at most 5s long, not caused by input
+    let windowValue = 0, windowStart = 0, lastShift = 0;
+    if (observe('layout-shift', entry => {
+        if (entry.hadRecentInput) return;
+        if (entry.startTime - lastShift > 1000 || entry.startTime - windowStart > 5000) {
+            windowValue = 0;
+            windowStart = entry.startTime;
+        }
+        windowValue += entry.value;
+        lastShift = entry.startTime;
+        state.cls = Math.max(state.cls || 0, windowValue);
+    })) state.cls = 0;
+    observe('longtask', entry => state.longTasks.push({ start: entry.startTime, duration: entry.duration }));
+"#;
+
+/// Reads Navigation Timing and first contentful paint, and what the observers recorded
+const COLLECT_PERFORMANCE_SCRIPT: &str = r#"
+    const [nav] = performance.getEntriesByType('navigation');
+    if (!nav) return null;
+    const state = window.__webAuditPerformance || { lcp: null, cls: null, longTasks: [] };
+    const fcp = performance.getEntriesByName('first-contentful-paint')[0];
+    const fcpMs = fcp ? fcp.startTime : null;
+    // Total blocking time: the part of every long task after first paint beyond 50ms
+    const blocking = state.longTasks
+        .filter(task => fcpMs === null || task.start >= fcpMs)
+        .reduce((sum, task) => sum + Math.max(0, task.duration - 50), 0);
+    return {
+        ttfb_ms: nav.responseStart,
+        dom_content_loaded_ms: nav.domContentLoadedEventEnd,
+        load_ms: nav.loadEventEnd,
+        fcp_ms: fcpMs,
+        lcp_ms: state.lcp,
+        cls: state.cls,
+        long_tasks: state.longTasks.length,
+        total_blocking_time_ms: blocking,
+    };
+"#;
+
+/// Lab metrics of a page load. Times are milliseconds since navigation start; paint metrics and CLS
+/// are `None` where the browser doesn't support them.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PageTimings {
+    pub ttfb_ms: f64,
+    pub dom_content_loaded_ms: f64,
+    pub load_ms: f64,
+    /// First contentful paint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fcp_ms: Option<f64>,
+    /// Largest contentful paint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lcp_ms: Option<f64>,
+    /// Cumulative layout shift
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cls: Option<f64>,
+    /// Tasks that kept the main thread busy for more than 50ms
+    pub long_tasks: usize,
+    pub total_blocking_time_ms: f64,
+}
+
+impl PageTimings {
+    fn metrics(&self) -> [(&'static str, Option<f64>); 6] {
+        [
+            ("TTFB", Some(self.ttfb_ms)),
+            ("FCP", self.fcp_ms),
+            ("LCP", self.lcp_ms),
+            ("CLS", self.cls),
+            ("Load", Some(self.load_ms)),
+            ("TBT", Some(self.total_blocking_time_ms)),
+        ]
+    }
+}
+
+/// Starts observing the page that was just opened, before waiting for it to settle
+pub async fn install_performance_observers(client: &mut Client) -> Result<(), CmdError> {
+    client.execute(INSTALL_PERFORMANCE_SCRIPT, Vec::new()).await?;
+    Ok(())
+}
+
+pub async fn collect_page_timings(client: &mut Client) -> Result<Option<PageTimings>, CmdError> {
+    let value = client.execute(COLLECT_PERFORMANCE_SCRIPT, Vec::new()).await?;
+    Ok(serde_json::from_value(value).ok())
+}
+
+/// Upper limits for the pages matching `pattern`. Unset metrics aren't checked.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct PerformanceBudget {
+    #[serde(default = "every_page")]
+    pub pattern: String,
+    pub ttfb_ms: Option<f64>,
+    pub fcp_ms: Option<f64>,
+    pub lcp_ms: Option<f64>,
+    pub cls: Option<f64>,
+    pub load_ms: Option<f64>,
+    pub total_blocking_time_ms: Option<f64>,
+    /// Page weight limits in bytes, see `PageResources`
+    pub transfer_bytes: Option<u64>,
+    pub js_bytes: Option<u64>,
+    pub image_bytes: Option<u64>,
+    pub third_party_bytes: Option<u64>,
+    pub requests: Option<usize>,
+}
+
+fn every_page() -> String {
+    "*".to_string()
+}
+
+impl PerformanceBudget {
+    fn limits(&self) -> [Option<f64>; 6] {
+        [
+            self.ttfb_ms,
+            self.fcp_ms,
+            self.lcp_ms,
+            self.cls,
+            self.load_ms,
+            self.total_blocking_time_ms,
+        ]
+    }
+
+    /// A finding for every timing over its limit
+    pub fn timing_findings(&self, timings: &PageTimings) -> Vec<Finding> {
+        timings
+            .metrics()
+            .into_iter()
+            .zip(self.limits())
+            .filter_map(|((metric, value), limit)| {
+                let (value, limit) = (value?, limit?);
+                if value <= limit {
+                    return None;
+                }
+                let message = if metric == "CLS" {
+                    format!("CLS {:.3} is over the budget of {}", value, limit)
+                } else {
+                    format!("{} {:.0}ms is over the budget of {}ms", metric, value, limit)
+                };
+                Some(Finding::new(BUDGET_RULE, Severity::Warning, message))
+            })
+            .collect()
+    }
+
+    /// A finding for every page weight limit that was exceeded
+    pub fn weight_findings(&self, resources: &PageResources) -> Vec<Finding> {
+        let weights = [
+            ("Page weight", resources.transfer_bytes, self.transfer_bytes),
+            ("JavaScript", resources.bytes_of(AssetType::Js), self.js_bytes),
+            ("Images", resources.bytes_of(AssetType::Image), self.image_bytes),
+            ("Third party resources", resources.third_party.bytes, self.third_party_bytes),
+        ];
+        let mut findings: Vec<Finding> = weights
+            .into_iter()
+            .filter_map(|(what, bytes, limit)| {
+                let limit = limit.filter(|limit| bytes > *limit)?;
+                Some(Finding::new(
+                    BUDGET_RULE,
+                    Severity::Warning,
+                    format!("{} of {} KB is over the budget of {} KB", what, bytes / 1024, limit / 1024),
+                ))
+            })
+            .collect();
+        if let Some(limit) = self.requests.filter(|limit| resources.requests > *limit) {
+            findings.push(Finding::new(
+                BUDGET_RULE,
+                Severity::Warning,
+                format!("{} requests are over the budget of {}", resources.requests, limit),
+            ));
+        }
+        findings
+    }
+}
+
+/// The `[performance]` section of the config file
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PerformanceOptions {
+    /// The first budget whose pattern matches a page applies to it. None by default, so pages are
+    /// only checked against budgets the config sets.
+    pub budgets: Vec<PerformanceBudget>,
+    /// Url patterns to report percentiles for, next to the whole site
+    pub summary_patterns: Vec<String>,
+}
+
+/// Checks every page's timings and weight against the budget for its url
+pub struct BudgetRule {
+    budgets: Vec<PerformanceBudget>,
+}
+
+impl BudgetRule {
+    pub fn new(budgets: Vec<PerformanceBudget>) -> BudgetRule {
+        BudgetRule { budgets }
+    }
+}
+
+#[async_trait]
+impl Rule for BudgetRule {
+    fn name(&self) -> &str {
+        BUDGET_RULE
+    }
+
+    async fn check(&self, page: &mut Page<'_>) -> Result<Vec<Finding>, WebScrapingError> {
+        let budget = self
+            .budgets
+            .iter()
+            .find(|budget| matches_pattern(&budget.pattern, &page.url.full_path));
+        let Some(budget) = budget else {
+            return Ok(Vec::new());
+        };
+        let mut findings = Vec::new();
+        if let Some(timings) = &page.timings {
+            findings.extend(budget.timing_findings(timings));
+        }
+        if let Some(resources) = &page.url.resources {
+            findings.extend(budget.weight_findings(resources));
+        }
+        Ok(findings)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Percentiles {
+    pub p50: f64,
+    pub p75: f64,
+    pub p95: f64,
+}
+
+impl Percentiles {
+    /// Nearest-rank percentiles, `None` without values
+    pub fn of(mut values: Vec<f64>) -> Option<Percentiles> {
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(|a, b| a.total_cmp(b));
+        let rank = |percentile: f64| {
+            let index = (percentile / 100.0 * values.len() as f64).ceil() as usize;
+            values[index.saturating_sub(1)]
+        };
+        Some(Percentiles {
+            p50: rank(50.0),
+            p75: rank(75.0),
+            p95: rank(95.0),
+        })
+    }
+}
+
+/// Percentiles of every metric over the pages matching a pattern
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct PerformanceGroup {
+    pub pattern: String,
+    pub pages: usize,
+    pub ttfb_ms: Option<Percentiles>,
+    pub fcp_ms: Option<Percentiles>,
+    pub lcp_ms: Option<Percentiles>,
+    pub cls: Option<Percentiles>,
+    pub load_ms: Option<Percentiles>,
+    pub total_blocking_time_ms: Option<Percentiles>,
+}
+
+/// The `performance` section of the report: the whole site first, then every summary pattern
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct PerformanceSummary {
+    pub groups: Vec<PerformanceGroup>,
+}
+
+pub fn performance_summary(index: &HashMap<String, Url>, patterns: &[String]) -> PerformanceSummary {
+    let groups = std::iter::once(&every_page())
+        .chain(patterns.iter())
+        .map(|pattern| {
+            let timings: Vec<&PageTimings> = index
+                .values()
+                .filter(|url| matches_pattern(pattern, &url.full_path))
+                .filter_map(|url| url.performance.as_ref())
+                .collect();
+            let metric = |value: fn(&PageTimings) -> Option<f64>| {
+                Percentiles::of(timings.iter().filter_map(|timings| value(timings)).collect())
+            };
+            PerformanceGroup {
+                pattern: pattern.clone(),
+                pages: timings.len(),
+                ttfb_ms: metric(|timings| Some(timings.ttfb_ms)),
+                fcp_ms: metric(|timings| timings.fcp_ms),
+                lcp_ms: metric(|timings| timings.lcp_ms),
+                cls: metric(|timings| timings.cls),
+                load_ms: metric(|timings| Some(timings.load_ms)),
+                total_blocking_time_ms: metric(|timings| Some(timings.total_blocking_time_ms)),
+            }
+        })
+        .collect();
+    PerformanceSummary { groups }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::weight::ResourceTotals;
+    use std::collections::BTreeMap;
+
+    fn timings(lcp_ms: f64, cls: Option<f64>) -> PageTimings {
+        PageTimings {
+            ttfb_ms: 200.0,
+            load_ms: 1500.0,
+            fcp_ms: Some(900.0),
+            lcp_ms: Some(lcp_ms),
+            cls,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn budget_findings_test() {
+        assert!(PerformanceOptions::default().budgets.is_empty());
+        let budget = PerformanceBudget {
+            ttfb_ms: Some(800.0),
+            lcp_ms: Some(2500.0),
+            cls: Some(0.1),
+            ..Default::default()
+        };
+        assert!(budget.timing_findings(&timings(2400.0, Some(0.05))).is_empty());
+
+        let messages: Vec<String> = budget
+            .timing_findings(&timings(3100.0, Some(0.25)))
+            .into_iter()
+            .map(|finding| finding.message)
+            .collect();
+        assert_eq!(
+            messages,
+            vec!["LCP 3100ms is over the budget of 2500ms", "CLS 0.250 is over the budget of 0.1"]
+        );
+        // Firefox doesn't report layout shifts
+        assert!(budget.timing_findings(&timings(1000.0, None)).is_empty());
+    }
+
+    #[test]
+    fn weight_findings_test() {
+        let budget = PerformanceBudget {
+            transfer_bytes: Some(1024 * 1024),
+            js_bytes: Some(300 * 1024),
+            requests: Some(50),
+            ..Default::default()
+        };
+        let resources = PageResources {
+            requests: 80,
+            transfer_bytes: 900 * 1024,
+            by_type: BTreeMap::from([(AssetType::Js, ResourceTotals { requests: 12, bytes: 450 * 1024 })]),
+            ..Default::default()
+        };
+
+        let messages: Vec<String> = budget
+            .weight_findings(&resources)
+            .into_iter()
+            .map(|finding| finding.message)
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "JavaScript of 450 KB is over the budget of 300 KB",
+                "80 requests are over the budget of 50"
+            ]
+        );
+    }
+
+    #[test]
+    fn performance_summary_test() {
+        let mut index = HashMap::new();
+        for (path, lcp) in [("/", 1000.0), ("/blog/a", 2000.0), ("/blog/b", 4000.0), ("/blog/c", 3000.0)] {
+            let full_path = format!("https://example.com{}", path);
+            let mut url = Url::new(full_path.clone(), Some(200), "https://example.com/".to_string());
+            url.performance = Some(timings(lcp, None));
+            index.insert(full_path, url);
+        }
+        index.insert(
+            "https://example.com/files/a.pdf".to_string(),
+            Url::new("https://example.com/files/a.pdf".to_string(), Some(200), "https://example.com/".to_string()),
+        );
+
+        let summary = performance_summary(&index, &["/blog/*".to_string()]);
+
+        assert_eq!(summary.groups[0].pattern, "*");
+        assert_eq!(summary.groups[0].pages, 4);
+        assert_eq!(summary.groups[1].pages, 3);
+        assert_eq!(
+            summary.groups[1].lcp_ms,
+            Some(Percentiles {
+                p50: 3000.0,
+                p75: 4000.0,
+                p95: 4000.0
+            })
+        );
+        assert_eq!(summary.groups[1].cls, None);
+    }
+}
@@ -0,0 +1,307 @@
+use crate::audit::a11y::AxeRule;
+use crate::audit::assertion::AssertionRule;
+use crate::audit::assets;
+use crate::audit::builtin;
+use crate::audit::canonical::{CANONICAL_CHAIN_RULE, HREFLANG_RULE};
+use crate::audit::images;
+use crate::audit::performance::{BudgetRule, PageTimings};
+use crate::audit::script::ScriptRule;
+use crate::audit::security;
+use crate::audit::seo;
+use crate::config::Config;
+use crate::webdriver::session::is_session_dead;
+use crate::webscraper::find_urls::{Url, WebScrapingError};
+use async_trait::async_trait;
+use fantoccini::Client;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// Something a rule found wrong with a page
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Finding {
+    pub rule: String,
+    pub severity: Severity,
+    pub message: String,
+    /// CSS selector of the offending element, when there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locator: Option<String>,
+    /// Where to read more about the problem and how to fix it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub help_url: Option<String>,
+}
+
+impl Finding {
+    pub fn new(rule: &str, severity: Severity, message: String) -> Finding {
+        Finding {
+            rule: rule.to_string(),
+            severity,
+            message,
+            locator: None,
+            help_url: None,
+        }
+    }
+
+    pub fn at(mut self, locator: String) -> Finding {
+        self.locator = Some(locator);
+        self
+    }
+}
+
+/// What a rule gets to look at: the crawled url, the browser showing the page, its links,
+/// the response headers (only fetched when a rule asks for them) and the load timings.
+pub struct Page<'a> {
+    pub url: &'a Url,
+    pub client: &'a mut Client,
+    /// The href of every anchor on the page, as written
+    pub links: &'a [String],
+    /// Lowercase header names
+    pub headers: &'a BTreeMap<String, String>,
+    pub timings: Option<PageTimings>,
+}
+
+/// A per-page audit. Rules run after a page finished loading, in registration order.
+#[async_trait]
+pub trait Rule: Send + Sync {
+    /// Name used in the config file and on findings
+    fn name(&self) -> &str;
+
+    /// Whether the page's response headers have to be requested over HTTP
+    fn needs_headers(&self) -> bool {
+        false
+    }
+
+    async fn check(&self, page: &mut Page<'_>) -> Result<Vec<Finding>, WebScrapingError>;
+}
+
+/// A `[rules.<name>]` table from the config file. Everything but `enabled` is handed to the rule.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct RuleConfig {
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub options: BTreeMap<String, Value>,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+impl Default for RuleConfig {
+    fn default() -> Self {
+        RuleConfig {
+            enabled: true,
+            options: BTreeMap::new(),
+        }
+    }
+}
+
+impl RuleConfig {
+    /// Deserializes the options into the rule's own settings struct
+    pub fn options<T: DeserializeOwned>(&self, rule: &str) -> Result<T, WebScrapingError> {
+        let options = Value::Object(self.options.clone().into_iter().collect());
+        serde_json::from_value(options)
+            .map_err(|e| WebScrapingError::ConfigError(format!("invalid options for rule {}: {}", rule, e)))
+    }
+}
+
+type RuleFactory = fn(&RuleConfig) -> Result<Arc<dyn Rule>, WebScrapingError>;
+
+/// Every built-in rule by name, in the order they run
+const BUILTIN_RULES: &[(&str, RuleFactory)] = &[
+    ("http-error", builtin::HttpError::from_config),
+    ("slow-page", builtin::SlowPage::from_config),
+    ("x-robots-noindex", builtin::XRobotsNoindex::from_config),
+    ("duplicate-id", builtin::DuplicateId::from_config),
+    (seo::SEO_RULE, seo::SeoMetadata::from_config),
+    (assets::BROKEN_ASSET_RULE, assets::BrokenAssets::from_config),
+    (images::IMAGE_RULE, images::ImageOptimization::from_config),
+    (security::SECURITY_HEADERS_RULE, security::SecurityHeaders::from_config),
+];
+
+/// Rules that only look at the finished crawl, run by `index-urls` after the last page
+const SITE_RULES: &[&str] = &[HREFLANG_RULE, CANONICAL_CHAIN_RULE];
+
+/// The rules run on every page
+#[derive(Clone, Default)]
+pub struct RuleSet {
+    rules: Vec<Arc<dyn Rule>>,
+}
+
+impl fmt::Debug for RuleSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+impl RuleSet {
+    /// The built-in rules, minus the ones disabled in the config, and the performance budgets,
+    /// followed by the user scripts, the in-browser assertions and axe-core when `[a11y]` is enabled
+    pub fn from_config(config: &Config) -> Result<RuleSet, WebScrapingError> {
+        let is_known = |name: &&String| {
+            BUILTIN_RULES.iter().any(|(builtin, _)| builtin == *name) || SITE_RULES.contains(&name.as_str())
+        };
+        if let Some(unknown) = config.rules.keys().find(|name| !is_known(name)) {
+            return Err(WebScrapingError::ConfigError(format!("unknown rule {}", unknown)));
+        }
+
+        let mut rule_set = RuleSet::default();
+        for (name, factory) in BUILTIN_RULES {
+            let rule_config = config.rule(name);
+            if rule_config.enabled {
+                rule_set.rules.push(factory(&rule_config)?);
+            }
+        }
+        if !config.performance.budgets.is_empty() {
+            rule_set.rules.push(Arc::new(BudgetRule::new(config.performance.budgets.clone())));
+        }
+        for script in config.scripts.iter() {
+            rule_set.rules.push(Arc::new(ScriptRule::load(script)?));
+        }
+        for assertion in config.assertions.iter() {
+            rule_set.rules.push(Arc::new(AssertionRule::load(assertion)?));
+        }
+        if config.a11y.enabled {
+            rule_set.rules.push(Arc::new(AxeRule::new(&config.a11y)?));
+        }
+        Ok(rule_set)
+    }
+
+    /// Adds a custom rule, run after the ones already in the set
+    pub fn add(&mut self, rule: Arc<dyn Rule>) {
+        self.rules.push(rule);
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.rules.iter().map(|rule| rule.name()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Whether any rule looks at response headers, which the browser doesn't expose
+    pub fn needs_headers(&self) -> bool {
+        self.rules.iter().any(|rule| rule.needs_headers())
+    }
+
+    /// Runs every rule on the page currently open in `web_client`. A failing rule is skipped,
+    /// unless the browser is gone, so the session can be restarted.
+    pub async fn check(
+        &self,
+        url: &Url,
+        links: &[String],
+        web_client: &mut Client,
+    ) -> Result<Vec<Finding>, WebScrapingError> {
+        if self.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut page = Page {
+            url,
+            client: web_client,
+            links,
+            headers: &url.headers,
+            timings: url.performance.clone(),
+        };
+
+        let mut findings = Vec::new();
+        for rule in self.rules.iter() {
+            match rule.check(&mut page).await {
+                Ok(rule_findings) => findings.extend(rule_findings),
+                Err(WebScrapingError::FantocciniCmdErrorr(e)) if is_session_dead(&e) => {
+                    return Err(WebScrapingError::FantocciniCmdErrorr(e))
+                }
+                Err(e) => println!("Trouble running rule {} on {}: {}", rule.name(), url.full_path, e),
+            }
+        }
+        Ok(findings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rule_set_from_config_test() {
+        let all = RuleSet::from_config(&Config::default()).unwrap();
+        assert_eq!(
+            all.names(),
+            vec![
+                "http-error",
+                "slow-page",
+                "x-robots-noindex",
+                "duplicate-id",
+                "seo-metadata",
+                "broken-asset",
+                "image-optimization",
+                "security-headers"
+            ]
+        );
+
+        let config: Config = toml::from_str(
+            r#"
+            [rules.duplicate-id]
+            enabled = false
+
+            [rules.slow-page]
+            max_load_ms = 1500
+
+            [[performance.budgets]]
+            lcp_ms = 2500
+            "#,
+        )
+        .unwrap();
+        let some = RuleSet::from_config(&config).unwrap();
+        assert_eq!(
+            some.names(),
+            vec![
+                "http-error",
+                "slow-page",
+                "x-robots-noindex",
+                "seo-metadata",
+                "broken-asset",
+                "image-optimization",
+                "security-headers",
+                "performance-budget"
+            ]
+        );
+
+        let unknown: Config = toml::from_str("[rules.no-such-rule]").unwrap();
+        assert!(RuleSet::from_config(&unknown).is_err());
+
+        let invalid: Config = toml::from_str("[rules.slow-page]\nmax_load_ms = \"fast\"").unwrap();
+        assert!(RuleSet::from_config(&invalid).is_err());
+
+        let missing_script: Config = toml::from_str("[[assertions]]\npattern = \"*\"\nscript = \"missing.js\"").unwrap();
+        assert!(RuleSet::from_config(&missing_script).is_err());
+    }
+
+    #[test]
+    fn finding_serialize_test() {
+        let finding = Finding::new("duplicate-id", Severity::Warning, "id main is used 2 times".to_string())
+            .at("#main".to_string());
+
+        assert_eq!(
+            serde_json::to_value(&finding).unwrap(),
+            serde_json::json!({
+                "rule": "duplicate-id",
+                "severity": "warning",
+                "message": "id main is used 2 times",
+                "locator": "#main",
+            })
+        );
+    }
+}
@@ -0,0 +1,281 @@
+use crate::audit::rule::{Finding, Page, Rule, Severity};
+use crate::webscraper::find_urls::{Url, WebScrapingError};
+use crate::webscraper::url_pattern::matches_pattern;
+use async_trait::async_trait;
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope};
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+/// Upper bound on the work a single script run may do, so a runaway loop can't stall the crawl
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// A `[[scripts]]` entry from the config file
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ScriptConfig {
+    /// Name used on findings, defaults to the file name without extension
+    #[serde(default)]
+    pub name: Option<String>,
+    pub path: String,
+    /// Url patterns the script runs on, every page by default
+    #[serde(default = "every_page")]
+    pub patterns: Vec<String>,
+}
+
+fn every_page() -> Vec<String> {
+    vec!["*".to_string()]
+}
+
+/// A rule written in Rhai. The script sees `url`, `status`, `title`, `links` and `meta` (name or
+/// property to content) and can call `query(selector)`, which returns the matching elements of the
+/// page as maps with `tag`, `text`, `attrs` and `selector`. It evaluates to a finding, an array of
+/// findings or `()`. A finding is a message string, or a map with `message` and optionally
+/// `severity` ("info", "warning" or "error") and `locator`.
+///
+/// ```rhai
+/// query("img").filter(|img| !("alt" in img.attrs))
+///     .map(|img| #{ message: "Image without alt text", locator: img.selector })
+/// ```
+pub struct ScriptRule {
+    name: String,
+    source: String,
+    patterns: Vec<String>,
+}
+
+impl ScriptRule {
+    /// Reads and compiles the script, so syntax errors show up before the crawl starts
+    pub fn load(config: &ScriptConfig) -> Result<ScriptRule, WebScrapingError> {
+        let source = fs::read_to_string(Path::new(&config.path))
+            .map_err(|e| WebScrapingError::ConfigError(format!("could not read {}: {}", config.path, e)))?;
+        let name = match &config.name {
+            Some(name) => name.clone(),
+            None => Path::new(&config.path)
+                .file_stem()
+                .map_or(config.path.clone(), |stem| stem.to_string_lossy().to_string()),
+        };
+        ScriptRule::new(name, source, config.patterns.clone())
+    }
+
+    pub fn new(name: String, source: String, patterns: Vec<String>) -> Result<ScriptRule, WebScrapingError> {
+        sandboxed_engine(&name)
+            .compile(&source)
+            .map_err(|e| WebScrapingError::ConfigError(format!("rule script {} does not compile: {}", name, e)))?;
+        Ok(ScriptRule { name, source, patterns })
+    }
+
+    /// Evaluates the script against the page html. Kept synchronous: the parsed document and the
+    /// engine are not `Send` and must not live across an await.
+    fn run(&self, url: &Url, links: &[String], html: &str) -> Result<Vec<Finding>, WebScrapingError> {
+        let document = Rc::new(Html::parse_document(html));
+        let mut engine = sandboxed_engine(&self.name);
+        let query_document = document.clone();
+        engine.register_fn("query", move |selector: &str| query(&query_document, selector));
+
+        let mut scope = Scope::new();
+        scope.push("url", url.full_path.clone());
+        scope.push_dynamic("status", url.response_code.map_or(Dynamic::UNIT, |code| Dynamic::from(code as i64)));
+        scope.push_dynamic("title", url.title.clone().map_or(Dynamic::UNIT, Dynamic::from));
+        scope.push("links", links.iter().cloned().map(Dynamic::from).collect::<Array>());
+        scope.push("meta", meta_tags(&document));
+
+        let result = engine
+            .eval_with_scope::<Dynamic>(&mut scope, &self.source)
+            .map_err(|e| WebScrapingError::ScriptError(format!("{}: {}", self.name, e)))?;
+        to_findings(&self.name, result).map_err(|e| WebScrapingError::ScriptError(format!("{}: {}", self.name, e)))
+    }
+}
+
+#[async_trait]
+impl Rule for ScriptRule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn check(&self, page: &mut Page<'_>) -> Result<Vec<Finding>, WebScrapingError> {
+        if !self.patterns.iter().any(|pattern| matches_pattern(pattern, &page.url.full_path)) {
+            return Ok(Vec::new());
+        }
+        let html = page.client.source().await?;
+        self.run(page.url, page.links, &html)
+    }
+}
+
+/// An engine without file access, imports or eval, and with bounded run time
+fn sandboxed_engine(name: &str) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(32);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(1_000_000);
+    engine.set_max_array_size(100_000);
+    engine.set_max_map_size(100_000);
+    let name = name.to_string();
+    engine.on_print(move |text| println!("[{}] {}", name, text));
+    engine
+}
+
+fn query(document: &Html, selector: &str) -> Result<Array, Box<EvalAltResult>> {
+    let parsed = Selector::parse(selector).map_err(|e| format!("invalid selector '{}': {}", selector, e))?;
+    Ok(document.select(&parsed).map(|element| Dynamic::from(element_map(element))).collect())
+}
+
+fn element_map(element: ElementRef) -> Map {
+    let attrs: Map = element
+        .value()
+        .attrs()
+        .map(|(name, value)| (name.into(), Dynamic::from(value.to_string())))
+        .collect();
+    let mut map = Map::new();
+    map.insert("tag".into(), Dynamic::from(element.value().name().to_string()));
+    map.insert("text".into(), Dynamic::from(element.text().collect::<String>().trim().to_string()));
+    map.insert("attrs".into(), Dynamic::from(attrs));
+    map.insert("selector".into(), Dynamic::from(css_path(element)));
+    map
+}
+
+/// A selector for exactly this element, anchored at the nearest ancestor with an id
+pub fn css_path(element: ElementRef) -> String {
+    let mut parts = Vec::new();
+    let mut current = Some(element);
+    while let Some(element) = current {
+        let name = element.value().name();
+        if let Some(id) = element.value().id() {
+            parts.push(format!("#{}", id));
+            break;
+        }
+        if name == "html" {
+            parts.push(name.to_string());
+            break;
+        }
+        let position = element
+            .prev_siblings()
+            .filter_map(ElementRef::wrap)
+            .filter(|sibling| sibling.value().name() == name)
+            .count()
+            + 1;
+        parts.push(format!("{}:nth-of-type({})", name, position));
+        current = element.parent().and_then(ElementRef::wrap);
+    }
+    parts.reverse();
+    parts.join(" > ")
+}
+
+fn meta_tags(document: &Html) -> Map {
+    let selector = Selector::parse("meta[name], meta[property]").expect("valid selector");
+    document
+        .select(&selector)
+        .filter_map(|meta| {
+            let key = meta.value().attr("name").or_else(|| meta.value().attr("property"))?;
+            let content = meta.value().attr("content").unwrap_or("");
+            Some((key.to_lowercase().into(), Dynamic::from(content.to_string())))
+        })
+        .collect()
+}
+
+fn to_findings(rule: &str, value: Dynamic) -> Result<Vec<Finding>, String> {
+    if value.is_unit() {
+        Ok(Vec::new())
+    } else if value.is_array() {
+        value
+            .cast::<Array>()
+            .into_iter()
+            .map(|item| to_finding(rule, item))
+            .collect()
+    } else {
+        Ok(vec![to_finding(rule, value)?])
+    }
+}
+
+fn to_finding(rule: &str, value: Dynamic) -> Result<Finding, String> {
+    if value.is_string() {
+        return Ok(Finding::new(rule, Severity::Warning, value.to_string()));
+    }
+    let map = value
+        .try_cast::<Map>()
+        .ok_or("a finding must be a string or a map with a message")?;
+    let text = |key: &str| map.get(key).filter(|value| !value.is_unit()).map(|value| value.to_string());
+
+    let message = text("message").ok_or("a finding needs a message")?;
+    let severity = match text("severity").as_deref() {
+        None | Some("warning") => Severity::Warning,
+        Some("info") => Severity::Info,
+        Some("error") => Severity::Error,
+        Some(other) => return Err(format!("unknown severity '{}'", other)),
+    };
+    let mut finding = Finding::new(rule, severity, message);
+    finding.locator = text("locator");
+    Ok(finding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<html><head>
+        <title>Blog</title>
+        <meta name="description" content="All our posts">
+        <meta property="og:title" content="Blog">
+    </head><body>
+        <main id="content">
+            <p>Intro</p>
+            <p>Lorem ipsum <img src="a.png"> <img src="b.png" alt="B"></p>
+        </main>
+    </body></html>"#;
+
+    fn page_url() -> Url {
+        Url {
+            title: Some("Blog".to_string()),
+            ..Url::new("https://example.com/blog".to_string(), Some(200), "https://example.com/".to_string())
+        }
+    }
+
+    #[test]
+    fn script_rule_run_test() {
+        let rule = ScriptRule::new(
+            "missing-alt".to_string(),
+            r#"
+            let found = query("img").filter(|img| !("alt" in img.attrs))
+                .map(|img| #{ message: "Image without alt text", locator: img.selector, severity: "error" });
+            if meta["description"] == "" { found.push("No description"); }
+            if query("p").len() > 1 && status == 200 && links.len() == 1 { found.push("Lorem ipsum"); }
+            found
+            "#
+            .to_string(),
+            vec!["*".to_string()],
+        )
+        .unwrap();
+
+        let findings = rule.run(&page_url(), &["/about".to_string()], PAGE).unwrap();
+
+        assert_eq!(
+            findings,
+            vec![
+                Finding::new("missing-alt", Severity::Error, "Image without alt text".to_string())
+                    .at("#content > p:nth-of-type(2) > img:nth-of-type(1)".to_string()),
+                Finding::new("missing-alt", Severity::Warning, "Lorem ipsum".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn script_rule_errors_test() {
+        assert!(ScriptRule::new("broken".to_string(), "let x = ;".to_string(), every_page()).is_err());
+        assert!(ScriptRule::new("no-eval".to_string(), "eval(\"1\")".to_string(), every_page()).is_err());
+
+        let endless = ScriptRule::new("endless".to_string(), "loop {}".to_string(), every_page()).unwrap();
+        assert!(endless.run(&page_url(), &[], PAGE).is_err());
+
+        let bad_selector = ScriptRule::new("bad".to_string(), "query(\"p[\")".to_string(), every_page()).unwrap();
+        assert!(bad_selector.run(&page_url(), &[], PAGE).is_err());
+
+        let bad_severity =
+            ScriptRule::new("bad".to_string(), "#{ message: \"x\", severity: \"fatal\" }".to_string(), every_page())
+                .unwrap();
+        assert!(bad_severity.run(&page_url(), &[], PAGE).is_err());
+    }
+}
@@ -0,0 +1,388 @@
+use crate::audit::rule::{Finding, Page, Rule, RuleConfig, Severity};
+use crate::webscraper::find_urls::{Url, WebScrapingError};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+pub const SECURITY_HEADERS_RULE: &str = "security-headers";
+
+/// The columns of the header matrix
+const MATRIX_HEADERS: &[&str] = &[
+    "strict-transport-security",
+    "content-security-policy",
+    "x-content-type-options",
+    "referrer-policy",
+    "permissions-policy",
+    "x-frame-options",
+    "server",
+    "x-powered-by",
+];
+
+/// Headers that name the software serving the page
+const DISCLOSING_HEADERS: &[&str] = &["server", "x-powered-by", "x-aspnet-version", "x-aspnetmvc-version"];
+
+/// CSP directives that decide which scripts and plugins run, where a weak source is an XSS hole
+/// rather than a missed hardening
+const SCRIPT_DIRECTIVES: &[&str] = &["default-src", "script-src", "script-src-elem", "script-src-attr", "object-src"];
+
+/// Sources that allow any host
+const WILDCARD_SOURCES: &[&str] = &["*", "http:", "https:", "http://*", "https://*"];
+
+/// `[rules.security-headers]` options
+#[derive(Deserialize)]
+#[serde(default)]
+struct SecurityHeaderOptions {
+    /// Shortest accepted HSTS max-age, a year by default as the preload list asks for
+    min_hsts_max_age: u64,
+}
+
+impl Default for SecurityHeaderOptions {
+    fn default() -> Self {
+        SecurityHeaderOptions {
+            min_hsts_max_age: 31_536_000,
+        }
+    }
+}
+
+/// Every directive of a policy with its sources. Several policies, as sent in repeated headers,
+/// are joined with commas and all apply.
+fn csp_directives(policy: &str) -> Vec<(String, Vec<&str>)> {
+    policy
+        .split([';', ','])
+        .filter_map(|directive| {
+            let mut tokens = directive.split_whitespace();
+            let name = tokens.next()?.to_lowercase();
+            Some((name, tokens.collect()))
+        })
+        .collect()
+}
+
+/// Missing or weak HSTS, CSP, X-Content-Type-Options, Referrer-Policy, Permissions-Policy and
+/// frame protections, and headers disclosing the server's version
+pub struct SecurityHeaders {
+    options: SecurityHeaderOptions,
+}
+
+impl SecurityHeaders {
+    pub fn from_config(config: &RuleConfig) -> Result<Arc<dyn Rule>, WebScrapingError> {
+        Ok(Arc::new(SecurityHeaders {
+            options: config.options(SECURITY_HEADERS_RULE)?,
+        }))
+    }
+
+    fn findings(&self, url: &str, headers: &BTreeMap<String, String>) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        let mut flag = |severity, header: &str, message: String| {
+            findings.push(Finding::new(SECURITY_HEADERS_RULE, severity, message).at(header.to_string()))
+        };
+
+        // Browsers ignore HSTS over plain http
+        if url.starts_with("https://") {
+            match headers.get("strict-transport-security") {
+                None => flag(
+                    Severity::Warning,
+                    "strict-transport-security",
+                    "No Strict-Transport-Security header".to_string(),
+                ),
+                Some(hsts) => {
+                    let directives: Vec<String> =
+                        hsts.split(';').map(|directive| directive.trim().to_lowercase()).collect();
+                    let max_age = directives
+                        .iter()
+                        .find_map(|directive| directive.strip_prefix("max-age="))
+                        .and_then(|max_age| max_age.trim_matches('"').parse::<u64>().ok());
+                    match max_age {
+                        Some(max_age) if max_age >= self.options.min_hsts_max_age => {}
+                        Some(max_age) => flag(
+                            Severity::Warning,
+                            "strict-transport-security",
+                            format!(
+                                "Strict-Transport-Security max-age {} is shorter than {}",
+                                max_age, self.options.min_hsts_max_age
+                            ),
+                        ),
+                        None => flag(
+                            Severity::Warning,
+                            "strict-transport-security",
+                            format!("Strict-Transport-Security '{}' has no valid max-age", hsts),
+                        ),
+                    }
+                    if !directives.iter().any(|directive| directive == "includesubdomains") {
+                        flag(
+                            Severity::Info,
+                            "strict-transport-security",
+                            "Strict-Transport-Security doesn't includeSubDomains".to_string(),
+                        );
+                    }
+                }
+            }
+        }
+
+        let directives = headers.get("content-security-policy").map(|policy| csp_directives(policy));
+        match &directives {
+            None if headers.contains_key("content-security-policy-report-only") => flag(
+                Severity::Warning,
+                "content-security-policy",
+                "Only Content-Security-Policy-Report-Only is set, which reports but blocks nothing".to_string(),
+            ),
+            None => flag(
+                Severity::Warning,
+                "content-security-policy",
+                "No Content-Security-Policy header".to_string(),
+            ),
+            Some(directives) => {
+                if !directives.iter().any(|(name, _)| name == "default-src" || name == "script-src") {
+                    flag(
+                        Severity::Warning,
+                        "content-security-policy",
+                        "Content-Security-Policy has no default-src or script-src, so it doesn't restrict scripts"
+                            .to_string(),
+                    );
+                }
+                for (name, sources) in directives.iter() {
+                    let severity = if SCRIPT_DIRECTIVES.contains(&name.as_str()) {
+                        Severity::Warning
+                    } else {
+                        Severity::Info
+                    };
+                    // With a nonce or hash present browsers ignore 'unsafe-inline'
+                    let has_nonce_or_hash = sources
+                        .iter()
+                        .any(|source| source.starts_with("'nonce-") || source.starts_with("'sha"));
+                    let unsafe_inline = sources.iter().any(|source| source.eq_ignore_ascii_case("'unsafe-inline'"));
+                    if unsafe_inline && !has_nonce_or_hash {
+                        flag(
+                            severity,
+                            "content-security-policy",
+                            format!("Content-Security-Policy {} allows 'unsafe-inline'", name),
+                        );
+                    }
+                    if let Some(wildcard) = sources.iter().find(|source| WILDCARD_SOURCES.contains(source)) {
+                        flag(
+                            severity,
+                            "content-security-policy",
+                            format!("Content-Security-Policy {} allows any host with '{}'", name, wildcard),
+                        );
+                    }
+                }
+            }
+        }
+
+        match headers.get("x-content-type-options") {
+            Some(value) if value.trim().eq_ignore_ascii_case("nosniff") => {}
+            _ => flag(
+                Severity::Warning,
+                "x-content-type-options",
+                "X-Content-Type-Options is not 'nosniff'".to_string(),
+            ),
+        }
+
+        match headers.get("referrer-policy") {
+            None => flag(Severity::Info, "referrer-policy", "No Referrer-Policy header".to_string()),
+            Some(policy) if policy.to_lowercase().contains("unsafe-url") => flag(
+                Severity::Warning,
+                "referrer-policy",
+                "Referrer-Policy 'unsafe-url' sends full urls to every site, even over http".to_string(),
+            ),
+            Some(_) => {}
+        }
+
+        if !headers.contains_key("permissions-policy") {
+            flag(Severity::Info, "permissions-policy", "No Permissions-Policy header".to_string());
+        }
+
+        let has_frame_ancestors = directives
+            .iter()
+            .flatten()
+            .any(|(name, _)| name == "frame-ancestors");
+        let frame_options = headers.get("x-frame-options").map(|value| value.trim().to_lowercase());
+        if !has_frame_ancestors && !matches!(frame_options.as_deref(), Some("deny" | "sameorigin")) {
+            flag(
+                Severity::Warning,
+                "x-frame-options",
+                "Neither CSP frame-ancestors nor X-Frame-Options DENY or SAMEORIGIN, the page can be framed by any site"
+                    .to_string(),
+            );
+        }
+
+        for header in DISCLOSING_HEADERS {
+            if let Some(value) = headers.get(*header).filter(|value| value.chars().any(|c| c.is_ascii_digit())) {
+                flag(Severity::Info, header, format!("{} header discloses the version '{}'", header, value));
+            }
+        }
+        findings
+    }
+}
+
+#[async_trait]
+impl Rule for SecurityHeaders {
+    fn name(&self) -> &str {
+        SECURITY_HEADERS_RULE
+    }
+
+    fn needs_headers(&self) -> bool {
+        true
+    }
+
+    async fn check(&self, page: &mut Page<'_>) -> Result<Vec<Finding>, WebScrapingError> {
+        // No headers means fetching them failed, which was already reported
+        if page.headers.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(self.findings(&page.url.full_path, page.headers))
+    }
+}
+
+/// One value of a header and the pages sending it
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct HeaderValue {
+    pub value: String,
+    pub urls: Vec<String>,
+}
+
+/// A column of the header matrix, the most common value first
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct HeaderColumn {
+    pub header: String,
+    pub values: Vec<HeaderValue>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missing: Vec<String>,
+}
+
+/// The `security_headers` section of the report: what every page sends for each security header,
+/// grouped by value so a site with one configuration reads as one row per header
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct HeaderMatrix {
+    pub pages: usize,
+    pub headers: Vec<HeaderColumn>,
+}
+
+pub fn header_matrix(index: &HashMap<String, Url>) -> HeaderMatrix {
+    let pages: Vec<&Url> = index.values().filter(|url| !url.headers.is_empty()).collect();
+    let headers = MATRIX_HEADERS
+        .iter()
+        .map(|header| {
+            let mut values: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+            let mut missing = BTreeSet::new();
+            for url in pages.iter() {
+                match url.headers.get(*header) {
+                    Some(value) => values.entry(value).or_default().insert(&url.full_path),
+                    None => missing.insert(url.full_path.as_str()),
+                };
+            }
+            let mut values: Vec<HeaderValue> = values
+                .into_iter()
+                .map(|(value, urls)| HeaderValue {
+                    value: value.to_string(),
+                    urls: urls.into_iter().map(String::from).collect(),
+                })
+                .collect();
+            values.sort_by_key(|value| std::cmp::Reverse(value.urls.len()));
+            HeaderColumn {
+                header: header.to_string(),
+                values,
+                missing: missing.into_iter().map(String::from).collect(),
+            }
+        })
+        .collect();
+    HeaderMatrix {
+        pages: pages.len(),
+        headers,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn security_header_findings_test() {
+        let rule = SecurityHeaders {
+            options: SecurityHeaderOptions::default(),
+        };
+
+        let hardened = headers(&[
+            ("strict-transport-security", "max-age=63072000; includeSubDomains; preload"),
+            (
+                "content-security-policy",
+                "default-src 'self'; script-src 'self' 'nonce-r4nd0m' 'unsafe-inline'; frame-ancestors 'none'",
+            ),
+            ("x-content-type-options", "nosniff"),
+            ("referrer-policy", "strict-origin-when-cross-origin"),
+            ("permissions-policy", "camera=(), geolocation=()"),
+            ("server", "nginx"),
+        ]);
+        assert!(rule.findings("https://example.com/", &hardened).is_empty());
+
+        let weak = headers(&[
+            ("strict-transport-security", "max-age=86400"),
+            ("content-security-policy", "default-src *; style-src 'self' 'unsafe-inline'"),
+            ("x-frame-options", "ALLOW-FROM https://partner.com"),
+            ("server", "Apache/2.4.41 (Ubuntu)"),
+            ("x-powered-by", "PHP/7.4.3"),
+        ]);
+        let findings = rule.findings("https://example.com/", &weak);
+        let messages: Vec<&str> = findings.iter().map(|finding| finding.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Strict-Transport-Security max-age 86400 is shorter than 31536000",
+                "Strict-Transport-Security doesn't includeSubDomains",
+                "Content-Security-Policy default-src allows any host with '*'",
+                "Content-Security-Policy style-src allows 'unsafe-inline'",
+                "X-Content-Type-Options is not 'nosniff'",
+                "No Referrer-Policy header",
+                "No Permissions-Policy header",
+                "Neither CSP frame-ancestors nor X-Frame-Options DENY or SAMEORIGIN, the page can be framed by any site",
+                "server header discloses the version 'Apache/2.4.41 (Ubuntu)'",
+                "x-powered-by header discloses the version 'PHP/7.4.3'",
+            ]
+        );
+        assert_eq!(findings[2].severity, Severity::Warning);
+        assert_eq!(findings[3].severity, Severity::Info);
+        assert_eq!(findings[2].locator, Some("content-security-policy".to_string()));
+
+        let plain_http = rule.findings("http://example.com/", &headers(&[("x-content-type-options", "nosniff")]));
+        assert_eq!(plain_http[0].message, "No Content-Security-Policy header");
+    }
+
+    #[test]
+    fn header_matrix_test() {
+        let mut index = HashMap::new();
+        for (path, server) in [
+            ("https://example.com/", Some("nginx")),
+            ("https://example.com/a", Some("nginx")),
+            ("https://example.com/b", None),
+        ] {
+            let mut url = Url::new(path.to_string(), Some(200), "https://example.com/".to_string());
+            url.headers = headers(&[("content-type", "text/html")]);
+            if let Some(server) = server {
+                url.headers.insert("server".to_string(), server.to_string());
+            }
+            index.insert(path.to_string(), url);
+        }
+        index.insert(
+            "https://example.com/c".to_string(),
+            Url::new("https://example.com/c".to_string(), None, "https://example.com/".to_string()),
+        );
+
+        let matrix = header_matrix(&index);
+
+        assert_eq!(matrix.pages, 3);
+        assert_eq!(matrix.headers.len(), MATRIX_HEADERS.len());
+        let server = matrix.headers.iter().find(|column| column.header == "server").unwrap();
+        assert_eq!(
+            server.values,
+            vec![HeaderValue {
+                value: "nginx".to_string(),
+                urls: vec!["https://example.com/".to_string(), "https://example.com/a".to_string()],
+            }]
+        );
+        assert_eq!(server.missing, vec!["https://example.com/b".to_string()]);
+    }
+}
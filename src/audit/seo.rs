@@ -0,0 +1,335 @@
+use crate::audit::rule::{Finding, Page, Rule, RuleConfig, Severity};
+use crate::webscraper::find_urls::{Url, WebScrapingError};
+use crate::webscraper::resource::ResourceKind;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+pub const SEO_RULE: &str = "seo-metadata";
+
+/// `[rules.seo-metadata]` options
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SeoOptions {
+    /// Longer titles get cut off in search results
+    pub max_title_length: usize,
+    pub max_description_length: usize,
+}
+
+impl Default for SeoOptions {
+    fn default() -> Self {
+        SeoOptions {
+            max_title_length: 60,
+            max_description_length: 160,
+        }
+    }
+}
+
+/// Pages sharing the same title or description
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct DuplicateGroup {
+    pub value: String,
+    pub urls: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct CanonicalIssue {
+    pub url: String,
+    pub canonical: String,
+    /// Status of the canonical target
+    pub status: u16,
+    pub redirected_to: Option<String>,
+}
+
+/// The `seo` section of the report
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct SeoSummary {
+    pub pages_checked: usize,
+    pub missing_titles: Vec<String>,
+    pub long_titles: Vec<String>,
+    pub missing_descriptions: Vec<String>,
+    pub long_descriptions: Vec<String>,
+    pub missing_h1: Vec<String>,
+    pub multiple_h1: Vec<String>,
+    pub missing_open_graph: Vec<String>,
+    pub duplicate_titles: Vec<DuplicateGroup>,
+    pub duplicate_descriptions: Vec<DuplicateGroup>,
+    pub bad_canonicals: Vec<CanonicalIssue>,
+}
+
+/// Flags missing, empty and too long titles and descriptions and missing or repeated h1s
+pub struct SeoMetadata {
+    options: SeoOptions,
+}
+
+impl SeoMetadata {
+    pub fn from_config(config: &RuleConfig) -> Result<Arc<dyn Rule>, WebScrapingError> {
+        Ok(Arc::new(SeoMetadata {
+            options: config.options(SEO_RULE)?,
+        }))
+    }
+}
+
+#[async_trait]
+impl Rule for SeoMetadata {
+    fn name(&self) -> &str {
+        SEO_RULE
+    }
+
+    async fn check(&self, page: &mut Page<'_>) -> Result<Vec<Finding>, WebScrapingError> {
+        Ok(page_findings(page.url, &self.options))
+    }
+}
+
+/// Successfully loaded web pages, the only ones search engines care about
+fn is_seo_page(url: &Url) -> bool {
+    url.response_code == Some(200) && url.resource_kind.is_none_or(|kind| kind == ResourceKind::Html)
+}
+
+fn is_blank(value: &Option<String>) -> bool {
+    value.as_deref().is_none_or(|value| value.trim().is_empty())
+}
+
+fn is_too_long(value: &Option<String>, max: usize) -> bool {
+    value.as_deref().is_some_and(|value| value.trim().chars().count() > max)
+}
+
+pub fn page_findings(url: &Url, options: &SeoOptions) -> Vec<Finding> {
+    if !is_seo_page(url) {
+        return Vec::new();
+    }
+    let mut findings = Vec::new();
+    let mut flag = |severity, message: String| findings.push(Finding::new(SEO_RULE, severity, message));
+
+    if is_blank(&url.title) {
+        flag(Severity::Error, "Page has no title".to_string());
+    } else if is_too_long(&url.title, options.max_title_length) {
+        flag(
+            Severity::Warning,
+            format!("Title is longer than {} characters", options.max_title_length),
+        );
+    }
+    match &url.description {
+        None => flag(Severity::Warning, "Page has no meta description".to_string()),
+        Some(description) if description.trim().is_empty() => {
+            flag(Severity::Warning, "Meta description is empty".to_string())
+        }
+        _ if is_too_long(&url.description, options.max_description_length) => flag(
+            Severity::Warning,
+            format!(
+                "Meta description is longer than {} characters",
+                options.max_description_length
+            ),
+        ),
+        _ => {}
+    }
+    match url.h1s.len() {
+        0 => flag(Severity::Warning, "Page has no h1".to_string()),
+        1 => {}
+        count => flag(Severity::Warning, format!("Page has {} h1 headings", count)),
+    }
+    findings
+}
+
+/// Groups pages by a value, keeping only values used by more than one page
+fn duplicates<'a, F>(pages: &[&'a Url], value: F) -> Vec<DuplicateGroup>
+where
+    F: Fn(&'a Url) -> Option<&'a String>,
+{
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for page in pages {
+        if let Some(text) = value(page).map(|text| text.trim()).filter(|text| !text.is_empty()) {
+            groups.entry(text.to_string()).or_default().push(page.full_path.clone());
+        }
+    }
+    groups
+        .into_iter()
+        .filter(|(_, urls)| urls.len() > 1)
+        .map(|(value, mut urls)| {
+            urls.sort();
+            DuplicateGroup { value, urls }
+        })
+        .collect()
+}
+
+/// Looks a url up in the index, ignoring a trailing slash
+pub(crate) fn find_crawled<'a>(index: &'a HashMap<String, Url>, url: &str) -> Option<&'a Url> {
+    let trimmed = url.trim_end_matches('/');
+    index
+        .get(url)
+        .or_else(|| index.get(trimmed))
+        .or_else(|| index.get(&format!("{}/", trimmed)))
+}
+
+fn canonical_issues(index: &HashMap<String, Url>) -> Vec<CanonicalIssue> {
+    let mut issues: Vec<CanonicalIssue> = index
+        .values()
+        .filter(|url| is_seo_page(url) && !url.is_canonical())
+        .filter_map(|url| {
+            let canonical = url.canonical.clone()?;
+            // Canonicals to other sites, or to pages the crawl stopped before, can't be judged
+            let target = find_crawled(index, &canonical)?;
+            let status = target.response_code?;
+            let redirected = target.redirected_to.is_some() || (300..400).contains(&status);
+            if status == 200 && !redirected {
+                return None;
+            }
+            Some(CanonicalIssue {
+                url: url.full_path.clone(),
+                canonical,
+                status,
+                redirected_to: target.redirected_to.clone(),
+            })
+        })
+        .collect();
+    issues.sort_by(|a, b| a.url.cmp(&b.url));
+    issues
+}
+
+/// Checks metadata across the whole crawl: duplicate titles and descriptions between indexable pages
+/// and canonicals pointing at broken or redirected urls. Adds findings to the affected pages.
+pub fn analyze_seo(index: &mut HashMap<String, Url>, options: &SeoOptions) -> SeoSummary {
+    let mut summary = SeoSummary::default();
+    let mut pages: Vec<&Url> = index.values().filter(|url| is_seo_page(url)).collect();
+    pages.sort_by(|a, b| a.full_path.cmp(&b.full_path));
+
+    summary.pages_checked = pages.len();
+    for page in pages.iter() {
+        let url = page.full_path.clone();
+        if is_blank(&page.title) {
+            summary.missing_titles.push(url.clone());
+        } else if is_too_long(&page.title, options.max_title_length) {
+            summary.long_titles.push(url.clone());
+        }
+        if is_blank(&page.description) {
+            summary.missing_descriptions.push(url.clone());
+        } else if is_too_long(&page.description, options.max_description_length) {
+            summary.long_descriptions.push(url.clone());
+        }
+        match page.h1s.len() {
+            0 => summary.missing_h1.push(url.clone()),
+            1 => {}
+            _ => summary.multiple_h1.push(url.clone()),
+        }
+        if !page.open_graph.contains_key("og:title") {
+            summary.missing_open_graph.push(url);
+        }
+    }
+
+    // Pages that are noindex or canonicalised elsewhere are expected to repeat their originals
+    let indexable: Vec<&Url> = pages.into_iter().filter(|url| url.is_indexable() && url.is_canonical()).collect();
+    summary.duplicate_titles = duplicates(&indexable, |url| url.title.as_ref());
+    summary.duplicate_descriptions = duplicates(&indexable, |url| url.description.as_ref());
+    summary.bad_canonicals = canonical_issues(index);
+
+    for (kind, groups) in [("Title", &summary.duplicate_titles), ("Meta description", &summary.duplicate_descriptions)] {
+        for group in groups {
+            for url in group.urls.iter() {
+                if let Some(page) = index.get_mut(url) {
+                    page.findings.push(Finding::new(
+                        SEO_RULE,
+                        Severity::Warning,
+                        format!("{} is shared with {} other pages", kind, group.urls.len() - 1),
+                    ));
+                }
+            }
+        }
+    }
+    for issue in summary.bad_canonicals.iter() {
+        if let Some(page) = index.get_mut(&issue.url) {
+            let problem = match &issue.redirected_to {
+                Some(destination) => format!("redirects to {}", destination),
+                None => format!("responded with {}", issue.status),
+            };
+            page.findings.push(Finding::new(
+                SEO_RULE,
+                Severity::Error,
+                format!("Canonical {} {}", issue.canonical, problem),
+            ));
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(path: &str, title: &str, description: Option<&str>) -> (String, Url) {
+        let url = Url {
+            title: Some(title.to_string()),
+            description: description.map(String::from),
+            h1s: vec![title.to_string()],
+            resource_kind: Some(ResourceKind::Html),
+            ..Url::new(path.to_string(), Some(200), "https://example.com/".to_string())
+        };
+        (path.to_string(), url)
+    }
+
+    #[test]
+    fn page_findings_test() {
+        let (_, mut url) = page("https://example.com/", "Home", Some(""));
+        url.h1s.push("Welcome".to_string());
+        url.title = Some("A very long title that goes on and on about everything we sell".to_string());
+
+        let messages: Vec<String> = page_findings(&url, &SeoOptions::default())
+            .into_iter()
+            .map(|finding| finding.message)
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Title is longer than 60 characters",
+                "Meta description is empty",
+                "Page has 2 h1 headings"
+            ]
+        );
+
+        url.response_code = Some(404);
+        assert!(page_findings(&url, &SeoOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn analyze_seo_test() {
+        let mut index = HashMap::from([
+            page("https://example.com/a", "Shoes", Some("Buy shoes")),
+            page("https://example.com/b", "Shoes", Some("Buy boots")),
+            page("https://example.com/print", "Shoes", Some("Buy shoes")),
+            page("https://example.com/c", "Socks", None),
+            page("https://example.com/old", "Old", Some("Old page")),
+        ]);
+        index.get_mut("https://example.com/print").unwrap().canonical = Some("https://example.com/a".to_string());
+        index.get_mut("https://example.com/c").unwrap().canonical = Some("https://example.com/old/".to_string());
+        let old = index.get_mut("https://example.com/old").unwrap();
+        old.response_code = Some(301);
+        old.redirected_to = Some("https://example.com/new".to_string());
+
+        let summary = analyze_seo(&mut index, &SeoOptions::default());
+
+        assert_eq!(summary.pages_checked, 4);
+        assert_eq!(summary.missing_descriptions, vec!["https://example.com/c".to_string()]);
+        assert_eq!(
+            summary.duplicate_titles,
+            vec![DuplicateGroup {
+                value: "Shoes".to_string(),
+                urls: vec!["https://example.com/a".to_string(), "https://example.com/b".to_string()],
+            }]
+        );
+        assert!(summary.duplicate_descriptions.is_empty());
+        assert_eq!(
+            summary.bad_canonicals,
+            vec![CanonicalIssue {
+                url: "https://example.com/c".to_string(),
+                canonical: "https://example.com/old/".to_string(),
+                status: 301,
+                redirected_to: Some("https://example.com/new".to_string()),
+            }]
+        );
+        assert_eq!(
+            index["https://example.com/c"].findings[0].message,
+            "Canonical https://example.com/old/ redirects to https://example.com/new"
+        );
+        assert_eq!(index["https://example.com/b"].findings.len(), 1);
+    }
+}
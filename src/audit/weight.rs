@@ -0,0 +1,338 @@
+use crate::webscraper::find_urls::Url;
+use fantoccini::error::CmdError;
+use fantoccini::Client;
+use reqwest::Url as ParsedUrl;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// How many of the heaviest pages the report lists
+const HEAVIEST_PAGES: usize = 20;
+
+/// The browser keeps 250 resource timings by default and drops the rest, too few for heavy pages.
+/// Only works before the page loads anything, so it runs at the start of every document.
+pub const RESOURCE_TIMING_PRELOAD_SCRIPT: &str = r#"
+(function () {
+    if (window.__webAuditResourceTiming) return;
+    const timing = window.__webAuditResourceTiming = { full: false };
+    performance.setResourceTimingBufferSize(1000);
+    performance.addEventListener('resourcetimingbufferfull', () => { timing.full = true; });
+})();
+"#;
+
+/// Lists the document and every resource it loaded from the Resource Timing API. Without the
+/// preload script a page at the default buffer size has probably lost some.
+const COLLECT_RESOURCES_SCRIPT: &str = r#"
+    const size = entry => entry.encodedBodySize || entry.transferSize || 0;
+    const [nav] = performance.getEntriesByType('navigation');
+    const entries = performance.getEntriesByType('resource');
+    const timing = window.__webAuditResourceTiming;
+    return {
+        document_bytes: nav ? size(nav) : 0,
+        entries: entries.map(entry => ({
+            url: entry.name,
+            initiator: entry.initiatorType,
+            bytes: size(entry),
+        })),
+        buffer_full: timing ? timing.full : entries.length >= 250,
+    };
+"#;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AssetType {
+    Html,
+    Js,
+    Css,
+    Image,
+    Font,
+    Media,
+    Other,
+}
+
+impl AssetType {
+    /// Classifies by file extension first, since `initiatorType` names the element that asked for
+    /// the resource (a `link` can load css, fonts or images), and falls back to the initiator
+    pub fn classify(url: &str, initiator: &str) -> AssetType {
+        let path = ParsedUrl::parse(url).map_or(url.to_string(), |parsed| parsed.path().to_string());
+        let extension = path.rsplit_once('.').map(|(_, extension)| extension.to_lowercase());
+        match extension.as_deref() {
+            Some("js" | "mjs") => return AssetType::Js,
+            Some("css") => return AssetType::Css,
+            Some("png" | "jpg" | "jpeg" | "gif" | "webp" | "avif" | "svg" | "ico" | "bmp") => return AssetType::Image,
+            Some("woff" | "woff2" | "ttf" | "otf" | "eot") => return AssetType::Font,
+            Some("mp4" | "webm" | "mp3" | "ogg" | "wav" | "m4a") => return AssetType::Media,
+            Some("html" | "htm") => return AssetType::Html,
+            _ => {}
+        }
+        match initiator {
+            "script" => AssetType::Js,
+            "css" => AssetType::Css,
+            "img" | "image" => AssetType::Image,
+            "video" | "audio" => AssetType::Media,
+            "iframe" | "frame" => AssetType::Html,
+            _ => AssetType::Other,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ResourceTotals {
+    pub requests: usize,
+    pub bytes: u64,
+}
+
+impl ResourceTotals {
+    fn add(&mut self, bytes: u64) {
+        self.requests += 1;
+        self.bytes += bytes;
+    }
+}
+
+/// One resource a page loaded
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct PageAsset {
+    pub url: String,
+    #[serde(rename = "type")]
+    pub asset_type: AssetType,
+    pub bytes: u64,
+    /// Served from another site than the page
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub third_party: bool,
+}
+
+/// What a page weighs. Sizes are compressed body sizes as if nothing was cached; cross-origin
+/// resources without a `Timing-Allow-Origin` header count as requests of 0 bytes.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct PageResources {
+    /// Every request including the document itself
+    pub requests: usize,
+    pub transfer_bytes: u64,
+    pub by_type: BTreeMap<AssetType, ResourceTotals>,
+    pub third_party: ResourceTotals,
+    pub assets: Vec<PageAsset>,
+    /// The browser stopped recording resources, so the counts are a lower bound
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub buffer_full: bool,
+}
+
+#[derive(Deserialize)]
+struct ResourceEntry {
+    url: String,
+    initiator: String,
+    bytes: f64,
+}
+
+#[derive(Deserialize)]
+struct CollectedResources {
+    document_bytes: f64,
+    entries: Vec<ResourceEntry>,
+    #[serde(default)]
+    buffer_full: bool,
+}
+
+/// The last two labels of a host, close enough to tell first from third parties without a
+/// public suffix list
+fn site_of(url: &str) -> Option<String> {
+    let parsed = ParsedUrl::parse(url).ok()?;
+    let host = parsed.host_str()?;
+    let labels: Vec<&str> = host.rsplitn(3, '.').take(2).collect();
+    Some(labels.into_iter().rev().collect::<Vec<&str>>().join("."))
+}
+
+impl PageResources {
+    fn from_entries(page_url: &str, document_bytes: u64, entries: Vec<ResourceEntry>) -> PageResources {
+        let page_site = site_of(page_url);
+        let mut resources = PageResources {
+            requests: 1,
+            transfer_bytes: document_bytes,
+            by_type: BTreeMap::from([(AssetType::Html, ResourceTotals { requests: 1, bytes: document_bytes })]),
+            ..Default::default()
+        };
+
+        for entry in entries {
+            let bytes = entry.bytes.max(0.0) as u64;
+            let asset_type = AssetType::classify(&entry.url, &entry.initiator);
+            let third_party = site_of(&entry.url).is_some_and(|site| Some(site) != page_site);
+            resources.requests += 1;
+            resources.transfer_bytes += bytes;
+            resources.by_type.entry(asset_type).or_default().add(bytes);
+            if third_party {
+                resources.third_party.add(bytes);
+            }
+            resources.assets.push(PageAsset {
+                url: entry.url,
+                asset_type,
+                bytes,
+                third_party,
+            });
+        }
+        resources
+    }
+
+    pub fn bytes_of(&self, asset_type: AssetType) -> u64 {
+        self.by_type.get(&asset_type).map_or(0, |totals| totals.bytes)
+    }
+}
+
+/// Reads the resources the open page loaded so far
+pub async fn collect_page_resources(client: &mut Client, page_url: &str) -> Result<Option<PageResources>, CmdError> {
+    let value = client.execute(COLLECT_RESOURCES_SCRIPT, Vec::new()).await?;
+    Ok(serde_json::from_value::<CollectedResources>(value).ok().map(|collected| PageResources {
+        buffer_full: collected.buffer_full,
+        ..PageResources::from_entries(page_url, collected.document_bytes as u64, collected.entries)
+    }))
+}
+
+/// An asset and every page that loads it
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct InventoryAsset {
+    pub url: String,
+    #[serde(rename = "type")]
+    pub asset_type: AssetType,
+    pub bytes: u64,
+    pub third_party: bool,
+    pub pages: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct PageWeight {
+    pub url: String,
+    pub transfer_bytes: u64,
+    pub requests: usize,
+    /// Counts are a lower bound, see `PageResources::buffer_full`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub buffer_full: bool,
+}
+
+/// The `resources` section of the report: the heaviest pages and every unique asset, the most
+/// widely used first
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct ResourceInventory {
+    pub pages: usize,
+    pub heaviest_pages: Vec<PageWeight>,
+    pub assets: Vec<InventoryAsset>,
+}
+
+pub fn resource_inventory(index: &HashMap<String, Url>) -> ResourceInventory {
+    let mut assets: BTreeMap<&str, (&PageAsset, BTreeSet<&str>)> = BTreeMap::new();
+    let mut heaviest_pages = Vec::new();
+
+    for url in index.values() {
+        let Some(resources) = &url.resources else {
+            continue;
+        };
+        heaviest_pages.push(PageWeight {
+            url: url.full_path.clone(),
+            transfer_bytes: resources.transfer_bytes,
+            requests: resources.requests,
+            buffer_full: resources.buffer_full,
+        });
+        for asset in resources.assets.iter() {
+            let (known, pages) = assets.entry(&asset.url).or_insert_with(|| (asset, BTreeSet::new()));
+            // A cached response can report 0 bytes, keep the size of a load that had one
+            if asset.bytes > known.bytes {
+                *known = asset;
+            }
+            pages.insert(&url.full_path);
+        }
+    }
+
+    let pages = heaviest_pages.len();
+    heaviest_pages.sort_by(|a, b| b.transfer_bytes.cmp(&a.transfer_bytes).then(a.url.cmp(&b.url)));
+    heaviest_pages.truncate(HEAVIEST_PAGES);
+    let mut assets: Vec<InventoryAsset> = assets
+        .into_values()
+        .map(|(asset, pages)| InventoryAsset {
+            url: asset.url.clone(),
+            asset_type: asset.asset_type,
+            bytes: asset.bytes,
+            third_party: asset.third_party,
+            pages: pages.into_iter().map(String::from).collect(),
+        })
+        .collect();
+    assets.sort_by(|a, b| b.pages.len().cmp(&a.pages.len()).then(b.bytes.cmp(&a.bytes)));
+    ResourceInventory {
+        pages,
+        heaviest_pages,
+        assets,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(url: &str, initiator: &str, bytes: f64) -> ResourceEntry {
+        ResourceEntry {
+            url: url.to_string(),
+            initiator: initiator.to_string(),
+            bytes,
+        }
+    }
+
+    #[test]
+    fn page_resources_test() {
+        let resources = PageResources::from_entries(
+            "https://www.example.com/",
+            20_000,
+            vec![
+                entry("https://cdn.example.com/app.js?v=3", "script", 150_000.0),
+                entry("https://www.example.com/site.css", "link", 30_000.0),
+                entry("https://www.example.com/fonts/inter.woff2", "css", 40_000.0),
+                entry("https://www.example.com/api/cart", "fetch", 1_000.0),
+                entry("https://www.googletagmanager.com/gtm.js", "script", 0.0),
+            ],
+        );
+
+        assert_eq!(resources.requests, 6);
+        assert_eq!(resources.transfer_bytes, 241_000);
+        assert_eq!(resources.bytes_of(AssetType::Js), 150_000);
+        assert_eq!(resources.by_type[&AssetType::Js].requests, 2);
+        assert_eq!(resources.bytes_of(AssetType::Font), 40_000);
+        assert_eq!(resources.bytes_of(AssetType::Other), 1_000);
+        assert_eq!(resources.third_party, ResourceTotals { requests: 1, bytes: 0 });
+
+        let json = serde_json::to_string(&resources).unwrap();
+        assert!(json.contains(r#""by_type":{"html":{"requests":1,"bytes":20000}"#));
+        assert!(!json.contains("buffer_full"));
+        assert_eq!(serde_json::from_str::<PageResources>(&json).unwrap(), resources);
+    }
+
+    #[test]
+    fn resource_inventory_test() {
+        let mut index = HashMap::new();
+        for (path, entries) in [
+            ("https://example.com/", vec![entry("https://example.com/app.js", "script", 100.0)]),
+            (
+                "https://example.com/blog",
+                vec![
+                    entry("https://example.com/app.js", "script", 0.0),
+                    entry("https://example.com/hero.jpg", "img", 900.0),
+                ],
+            ),
+        ] {
+            let mut url = Url::new(path.to_string(), Some(200), "https://example.com/".to_string());
+            url.resources = Some(PageResources::from_entries(path, 50, entries));
+            index.insert(path.to_string(), url);
+        }
+        index.get_mut("https://example.com/blog").unwrap().resources.as_mut().unwrap().buffer_full = true;
+
+        let inventory = resource_inventory(&index);
+
+        assert_eq!(inventory.pages, 2);
+        assert_eq!(inventory.heaviest_pages[0].url, "https://example.com/blog");
+        assert_eq!(inventory.heaviest_pages[0].transfer_bytes, 950);
+        assert!(inventory.heaviest_pages[0].buffer_full);
+        assert!(!inventory.heaviest_pages[1].buffer_full);
+        assert_eq!(
+            inventory.assets[0],
+            InventoryAsset {
+                url: "https://example.com/app.js".to_string(),
+                asset_type: AssetType::Js,
+                bytes: 100,
+                third_party: false,
+                pages: vec!["https://example.com/".to_string(), "https://example.com/blog".to_string()],
+            }
+        );
+    }
+}
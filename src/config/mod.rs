@@ -0,0 +1,167 @@
+use crate::audit::a11y::A11yOptions;
+use crate::audit::assertion::AssertionConfig;
+use crate::audit::lighthouse::LighthouseOptions;
+use crate::audit::performance::PerformanceOptions;
+use crate::audit::rule::RuleConfig;
+use crate::audit::script::ScriptConfig;
+use crate::webdriver::capabilities::BrowserOptions;
+use crate::webscraper::find_urls::WebScrapingError;
+use crate::webscraper::page_load::PageLoadOptions;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Settings read from the file passed with --config. Command line flags win over the file.
+///
+/// ```toml
+/// [browser]
+/// headless = true
+/// window_size = { width = 1366, height = 768 }
+/// locale = "de-DE"
+///
+/// [browser.firefox_prefs]
+/// "media.autoplay.default" = 5
+///
+/// [crawl]
+/// recycle_after = 500
+///
+/// [crawl.page_load]
+/// timeouts = { page_load_ms = 20000 }
+/// wait = { strategy = "network_idle", idle_ms = 500, timeout_ms = 10000 }
+///
+/// [[crawl.page_load.rules]]
+/// pattern = "/docs/*"
+/// wait = { strategy = "selector", selector = "nav a", timeout_ms = 10000 }
+///
+/// [rules.duplicate-id]
+/// enabled = false
+///
+/// [rules.slow-page]
+/// max_load_ms = 2000
+///
+/// [[scripts]]
+/// path = "rules/no_lorem_ipsum.rhai"
+/// patterns = ["/blog/*"]
+///
+/// [[assertions]]
+/// pattern = "/product/*"
+/// script = "assertions/product.js"
+///
+/// [performance]
+/// summary_patterns = ["/blog/*"]
+///
+/// [[performance.budgets]]
+/// pattern = "/product/*"
+/// lcp_ms = 2000
+/// cls = 0.05
+///
+/// [a11y]
+/// enabled = true
+/// tags = ["wcag2a", "wcag2aa"]
+///
+/// [lighthouse]
+/// command = "npx lighthouse"
+/// concurrency = 4
+/// ```
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub browser: BrowserOptions,
+    pub crawl: CrawlOptions,
+    /// Built-in audit rules to disable or configure, by name
+    pub rules: BTreeMap<String, RuleConfig>,
+    /// Rhai rule scripts run on the pages matching their patterns
+    pub scripts: Vec<ScriptConfig>,
+    /// JavaScript run in the browser on the pages matching their pattern
+    pub assertions: Vec<AssertionConfig>,
+    /// Performance budgets and percentile reporting
+    pub performance: PerformanceOptions,
+    /// The axe-core accessibility audit
+    pub a11y: A11yOptions,
+    /// How `audit lighthouse` runs Lighthouse
+    pub lighthouse: LighthouseOptions,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CrawlOptions {
+    /// Restart the browser after this many pages to bound its memory use
+    pub recycle_after: Option<usize>,
+    /// Classify urls by file extension only, without sending a HEAD request for urls that have none
+    pub extension_only: bool,
+    /// Timeouts and what to wait for before a page is scraped
+    pub page_load: PageLoadOptions,
+}
+
+impl Config {
+    /// The `[rules.<name>]` table, or the defaults when the rule isn't configured
+    pub fn rule(&self, name: &str) -> RuleConfig {
+        self.rules.get(name).cloned().unwrap_or_default()
+    }
+}
+
+pub fn parse_config(contents: &str) -> Result<Config, WebScrapingError> {
+    toml::from_str(contents).map_err(|e| WebScrapingError::ConfigError(e.to_string()))
+}
+
+/// Reads the config file, or the defaults when no file was given
+pub fn load_config(path: Option<&str>) -> Result<Config, WebScrapingError> {
+    match path {
+        Some(path) => match fs::read_to_string(Path::new(path)) {
+            Ok(contents) => parse_config(&contents),
+            Err(e) => Err(WebScrapingError::ConfigError(format!("could not read {}: {}", path, e))),
+        },
+        None => Ok(Config::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webdriver::capabilities::WindowSize;
+    use crate::webscraper::page_load::WaitStrategy;
+    use serde_json::json;
+
+    #[test]
+    fn parse_config_test() {
+        let config = parse_config(
+            r#"
+            [browser]
+            headless = true
+            window_size = { width = 1366, height = 768 }
+            args = ["--no-sandbox"]
+
+            [browser.firefox_prefs]
+            "media.autoplay.default" = 5
+
+            [crawl]
+            recycle_after = 500
+
+            [crawl.page_load]
+            wait = { strategy = "delay", ms = 200 }
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.browser.headless);
+        assert_eq!(
+            config.browser.window_size,
+            Some(WindowSize {
+                width: 1366,
+                height: 768
+            })
+        );
+        assert_eq!(config.browser.args, vec!["--no-sandbox".to_string()]);
+        assert_eq!(config.browser.firefox_prefs["media.autoplay.default"], json!(5));
+        assert!(!config.browser.disable_images);
+        assert_eq!(config.crawl.recycle_after, Some(500));
+        assert_eq!(config.crawl.page_load.wait, WaitStrategy::Delay { ms: 200 });
+    }
+
+    #[test]
+    fn parse_config_error_test() {
+        assert!(parse_config("[browser]\nheadless = \"yes\"").is_err());
+        assert_eq!(parse_config("").unwrap(), Config::default());
+    }
+}
@@ -0,0 +1,337 @@
+use crate::audit::rule::RuleSet;
+use crate::config::{Config, CrawlOptions};
+use crate::crawler::events::CrawlEvent;
+use crate::webdriver::capabilities::BrowserOptions;
+use crate::webdriver::session::{is_session_dead, BrowserSession};
+use crate::webdriver::webdriver::WebDriver;
+use crate::webscraper::fetch::http_client;
+use crate::webscraper::find_urls::{check_resource, find_all_urls_from_webpage, Url, WebScrapingError};
+use futures::channel::mpsc::{self, UnboundedSender};
+use futures::Stream;
+use std::collections::{HashMap, HashSet};
+
+/// How often the browser is restarted for a single url before giving up
+const MAX_SESSION_RESTARTS: usize = 2;
+
+/// Which browser the pages are loaded in
+#[derive(Debug, Clone, Default)]
+pub struct Fetcher {
+    pub driver: WebDriver,
+    pub browser_options: BrowserOptions,
+}
+
+/// When to stop crawling before every in-scope url was visited
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Limits {
+    /// Stop after this many urls were visited or checked
+    pub max_pages: Option<usize>,
+    /// Only follow links this many clicks away from the seeds
+    pub max_depth: Option<usize>,
+}
+
+/// How broken pages are recognised
+#[derive(Debug, Clone)]
+pub struct Detectors {
+    /// Pages whose title contains this text are recorded as 404s
+    pub not_found_title: String,
+    /// Audit rules run on every page after it loaded
+    pub rules: RuleSet,
+}
+
+impl Default for Detectors {
+    fn default() -> Self {
+        Detectors {
+            not_found_title: "Page Not Found".to_string(),
+            rules: RuleSet::from_config(&Config::default()).expect("built-in rules have valid defaults"),
+        }
+    }
+}
+
+/// A configured crawl. Build one with `Crawler::builder()` and consume it with `crawl()`.
+#[derive(Debug, Clone)]
+pub struct Crawler {
+    seeds: Vec<String>,
+    scope: Vec<String>,
+    fetcher: Fetcher,
+    limits: Limits,
+    detectors: Detectors,
+    crawl_options: CrawlOptions,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CrawlerBuilder {
+    seeds: Vec<String>,
+    scope: Vec<String>,
+    fetcher: Fetcher,
+    limits: Limits,
+    detectors: Detectors,
+    crawl_options: CrawlOptions,
+}
+
+impl CrawlerBuilder {
+    /// Adds a url the crawl starts from
+    pub fn seed(mut self, url: &str) -> Self {
+        self.seeds.push(url.to_string());
+        self
+    }
+
+    pub fn seeds<I: IntoIterator<Item = String>>(mut self, urls: I) -> Self {
+        self.seeds.extend(urls);
+        self
+    }
+
+    /// Domains (`example.com`) or url prefixes (`https://example.com/docs`) links are followed into.
+    /// Defaults to the seeds.
+    pub fn scope(mut self, domains: Vec<String>) -> Self {
+        self.scope = domains;
+        self
+    }
+
+    pub fn fetcher(mut self, fetcher: Fetcher) -> Self {
+        self.fetcher = fetcher;
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn detectors(mut self, detectors: Detectors) -> Self {
+        self.detectors = detectors;
+        self
+    }
+
+    /// Page load, recycling and resource settings, usually the `[crawl]` section of the config file
+    pub fn crawl_options(mut self, crawl_options: CrawlOptions) -> Self {
+        self.crawl_options = crawl_options;
+        self
+    }
+
+    pub fn build(self) -> Result<Crawler, WebScrapingError> {
+        if self.seeds.is_empty() {
+            return Err(WebScrapingError::ConfigError("a crawl needs at least one seed url".to_string()));
+        }
+        if self.crawl_options.recycle_after == Some(0) {
+            return Err(WebScrapingError::ConfigError("recycle_after must be at least 1".to_string()));
+        }
+        let scope = if self.scope.is_empty() {
+            self.seeds.clone()
+        } else {
+            self.scope
+        };
+        Ok(Crawler {
+            seeds: self.seeds,
+            scope,
+            fetcher: self.fetcher,
+            limits: self.limits,
+            detectors: self.detectors,
+            crawl_options: self.crawl_options,
+        })
+    }
+}
+
+/// A failed crawl, with the url that was being visited when known
+struct CrawlError {
+    url: Option<String>,
+    error: WebScrapingError,
+}
+
+impl From<WebScrapingError> for CrawlError {
+    fn from(error: WebScrapingError) -> Self {
+        CrawlError { url: None, error }
+    }
+}
+
+/// Sends a crawl event, failing once the receiver was dropped so the crawl stops
+fn send(events: &UnboundedSender<CrawlEvent>, event: CrawlEvent) -> Result<(), CrawlError> {
+    events
+        .unbounded_send(event)
+        .map_err(|_| WebScrapingError::CrawlCancelled.into())
+}
+
+impl Crawler {
+    pub fn builder() -> CrawlerBuilder {
+        CrawlerBuilder::default()
+    }
+
+    /// Starts the crawl on a new tokio task and streams what happens. The stream ends after
+    /// `CrawlEvent::Finished` or `CrawlEvent::Error`. Must be called within a tokio runtime.
+    /// Dropping the stream stops the crawl before the next page and closes the browser.
+    pub fn crawl(self) -> impl Stream<Item = CrawlEvent> {
+        let (sender, receiver) = mpsc::unbounded();
+        tokio::spawn(async move {
+            let event = match self.run(&sender).await {
+                Ok(index) => CrawlEvent::Finished(index),
+                Err(CrawlError { url, error }) => CrawlEvent::Error { url, error },
+            };
+            // Nobody is listening anymore when this fails, which is fine
+            let _ = sender.unbounded_send(event);
+        });
+        receiver
+    }
+
+    async fn run(&self, events: &UnboundedSender<CrawlEvent>) -> Result<HashMap<String, Url>, CrawlError> {
+        let http = http_client();
+        let mut session = BrowserSession::start(
+            self.fetcher.driver.clone(),
+            self.fetcher.browser_options.clone(),
+            self.crawl_options.page_load.timeouts.clone(),
+            self.crawl_options.recycle_after,
+        )
+        .await?;
+
+        let result = self.create_index(&mut session, &http, events).await;
+        let closed = session.close().await;
+        let index = result?;
+        closed?;
+        Ok(index)
+    }
+
+    /// Visits every known url that wasn't visited yet, round after round, until a round finds nothing new.
+    /// Urls visited in a round were discovered in the previous one, so the round is the click depth.
+    async fn create_index(
+        &self,
+        session: &mut BrowserSession,
+        http: &reqwest::Client,
+        events: &UnboundedSender<CrawlEvent>,
+    ) -> Result<HashMap<String, Url>, CrawlError> {
+        let mut url_index: HashMap<String, Url> = self
+            .seeds
+            .iter()
+            .map(|seed| (seed.clone(), Url::new(seed.clone(), None, seed.clone())))
+            .collect();
+        let mut url_list: HashSet<String> = self.seeds.iter().cloned().collect();
+        let mut pages_visited = 0;
+        let mut depth = 0;
+
+        loop {
+            let found_urls: HashSet<String> = url_list.clone();
+            let mut should_return = true;
+
+            println!("Looping through {} urls ", found_urls.len());
+            for url in found_urls {
+                // The caller dropped the stream: stop, so `run` shuts the browser down
+                if events.is_closed() {
+                    return Err(WebScrapingError::CrawlCancelled.into());
+                }
+                //All urls we iterator through should be found in the index
+                if let Some(url_object) = url_index.get_mut(&url) {
+                    //If Url contains Some response code we know we already visited this url
+                    if url_object.response_code.is_some() || url_object.resource_kind.is_some() {
+                        continue;
+                    } else {
+                        should_return = false;
+                    }
+                    if self.limits.max_pages.is_some_and(|max| pages_visited >= max) {
+                        println!("Stopping after {} pages", pages_visited);
+                        return Ok(url_index);
+                    }
+                    //Downloads, images and other files are only checked over HTTP
+                    let keep_headers = self.detectors.rules.needs_headers();
+                    if check_resource(http, url_object, self.crawl_options.extension_only, keep_headers).await {
+                        pages_visited += 1;
+                        send(events, CrawlEvent::PageVisited(Box::new(url_object.clone())))?;
+                        continue;
+                    }
+                }
+
+                let mut restarts = 0;
+                let found_urls_vec: Vec<String> = loop {
+                    match find_all_urls_from_webpage(
+                        &url,
+                        session.client(),
+                        self.scope.clone(),
+                        &mut url_index,
+                        &self.detectors,
+                        http,
+                        &self.crawl_options.page_load,
+                    )
+                    .await
+                    {
+                        // The browser crashed: start a new one and try this url again
+                        Err(WebScrapingError::FantocciniCmdErrorr(e))
+                            if is_session_dead(&e) && restarts < MAX_SESSION_RESTARTS =>
+                        {
+                            println!("Browser session lost while visiting {}: {}", url, e);
+                            restarts += 1;
+                            session.restart().await?;
+                        }
+                        result => {
+                            break result.map_err(|error| CrawlError {
+                                url: Some(url.clone()),
+                                error,
+                            })?
+                        }
+                    }
+                };
+                pages_visited += 1;
+                session.page_visited().await?;
+
+                if let Some(url_object) = url_index.get(&url) {
+                    send(events, CrawlEvent::PageVisited(Box::new(url_object.clone())))?;
+                }
+                for link in found_urls_vec.iter() {
+                    send(
+                        events,
+                        CrawlEvent::LinkDiscovered {
+                            from: url.clone(),
+                            to: link.clone(),
+                        },
+                    )?;
+                }
+                //iterate through all urls and insert to HashSet.
+                url_list.extend(found_urls_vec);
+            }
+
+            depth += 1;
+            if should_return || self.limits.max_depth.is_some_and(|max| depth > max) {
+                return Ok(url_index);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crawler_builder_test() {
+        let crawler = Crawler::builder()
+            .seed("https://example.com/")
+            .limits(Limits {
+                max_pages: Some(10),
+                max_depth: None,
+            })
+            .build()
+            .unwrap();
+
+        assert_eq!(crawler.scope, vec!["https://example.com/".to_string()]);
+        assert_eq!(crawler.limits.max_pages, Some(10));
+        assert_eq!(crawler.detectors.not_found_title, "Page Not Found");
+        assert_eq!(crawler.fetcher.driver, WebDriver::GeckoDriver);
+
+        assert!(Crawler::builder().scope(vec!["example.com".to_string()]).build().is_err());
+        let recycle_every_page = CrawlOptions {
+            recycle_after: Some(0),
+            ..Default::default()
+        };
+        assert!(Crawler::builder()
+            .seed("https://example.com/")
+            .crawl_options(recycle_every_page)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn send_after_receiver_dropped_test() {
+        let (sender, receiver) = mpsc::unbounded();
+        assert!(send(&sender, CrawlEvent::Finished(HashMap::new())).is_ok());
+
+        drop(receiver);
+
+        let error = send(&sender, CrawlEvent::Finished(HashMap::new())).unwrap_err();
+        assert!(matches!(error.error, WebScrapingError::CrawlCancelled));
+    }
+}
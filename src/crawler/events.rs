@@ -0,0 +1,18 @@
+use crate::webscraper::find_urls::{Url, WebScrapingError};
+use std::collections::HashMap;
+
+/// What a running crawl reports, in the order it happens
+#[derive(Debug)]
+pub enum CrawlEvent {
+    /// A url was visited in the browser or, for files, checked over HTTP
+    PageVisited(Box<Url>),
+    /// An in-scope link to `to` was found on the page `from`
+    LinkDiscovered { from: String, to: String },
+    /// The crawl stopped; no `Finished` event follows
+    Error {
+        url: Option<String>,
+        error: WebScrapingError,
+    },
+    /// Every url seen during the crawl, including the ones not visited because of limits
+    Finished(HashMap<String, Url>),
+}
@@ -0,0 +1,40 @@
+//! Crawls a website in a real browser and audits what it finds.
+//!
+//! The `web_audit` binary is a thin command line wrapper around this library. To embed the
+//! crawler, build a [`Crawler`] and consume its event stream:
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use web_audit::{CrawlEvent, Crawler, Limits};
+//!
+//! # async fn run() -> Result<(), web_audit::WebScrapingError> {
+//! let crawler = Crawler::builder()
+//!     .seed("https://example.com/")
+//!     .limits(Limits { max_pages: Some(100), max_depth: None })
+//!     .build()?;
+//!
+//! let mut events = Box::pin(crawler.crawl());
+//! while let Some(event) = events.next().await {
+//!     match event {
+//!         CrawlEvent::PageVisited(url) => println!("{} {:?}", url.full_path, url.response_code),
+//!         CrawlEvent::Error { error, .. } => return Err(error),
+//!         _ => {}
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+pub mod audit;
+pub mod config;
+pub mod crawler;
+pub mod linkgraph;
+pub mod redirects;
+pub mod report;
+pub mod sitemap;
+pub mod webdriver;
+pub mod webscraper;
+
+pub use audit::rule::{Finding, Page, Rule, RuleSet, Severity};
+pub use crawler::crawler::{Crawler, CrawlerBuilder, Detectors, Fetcher, Limits};
+pub use crawler::events::CrawlEvent;
+pub use webscraper::find_urls::{Url, WebScrapingError};
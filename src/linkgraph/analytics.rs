@@ -0,0 +1,330 @@
+use crate::report::{update_report, Report};
+use crate::webscraper::find_urls::{read_from_file, write_to_file, Url, WebScrapingError};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+
+const DAMPING_FACTOR: f64 = 0.85;
+const MAX_ITERATIONS: usize = 100;
+const CONVERGENCE: f64 = 1e-9;
+const TOP_PAGES: usize = 20;
+
+/// Per page metrics computed from the internal link graph.
+/// Link counts are distinct internal pages, not individual anchors.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct LinkMetrics {
+    /// Fewest clicks needed to reach the page from the seed url. None if unreachable.
+    pub click_depth: Option<usize>,
+    pub inbound_links: usize,
+    pub outbound_links: usize,
+    pub page_rank: f64,
+    /// A 200 page without any outbound internal links
+    pub dead_end: bool,
+    /// Listed in the url list but no other page links to it
+    pub orphan: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct RankedPage {
+    pub url: String,
+    pub page_rank: f64,
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct LinkSummary {
+    pub seed: String,
+    pub pages: usize,
+    pub max_click_depth: usize,
+    /// click depth -> number of pages at that depth
+    pub click_depth_histogram: BTreeMap<usize, usize>,
+    pub unreachable: Vec<String>,
+    pub dead_ends: Vec<String>,
+    /// Urls from the url list that were never linked, including those the crawl never found
+    pub orphans: Vec<String>,
+    pub top_page_rank: Vec<RankedPage>,
+}
+
+/// Computes link metrics for every url in the index and returns the site-wide summary
+pub fn analyze_links(index: &mut HashMap<String, Url>, seed: &str, known_urls: &[String]) -> LinkSummary {
+    let edges = internal_edges(index);
+    let pages: Vec<String> = index.keys().cloned().collect();
+
+    let mut outbound: HashMap<&str, BTreeSet<&str>> = HashMap::new();
+    let mut inbound: HashMap<&str, BTreeSet<&str>> = HashMap::new();
+    for (source, target) in edges.iter() {
+        outbound.entry(source).or_default().insert(target);
+        inbound.entry(target).or_default().insert(source);
+    }
+
+    let depths = click_depths(seed, &outbound);
+    let ranks = page_rank(&pages, &outbound);
+    let known: BTreeSet<String> = known_urls.iter().map(|url| normalize(url)).collect();
+
+    let mut summary = LinkSummary {
+        seed: seed.to_string(),
+        pages: pages.len(),
+        ..Default::default()
+    };
+
+    for (key, url) in index.iter_mut() {
+        let outbound_links = outbound.get(key.as_str()).map_or(0, BTreeSet::len);
+        let inbound_links = inbound.get(key.as_str()).map_or(0, BTreeSet::len);
+        let click_depth = depths.get(key.as_str()).copied();
+        let metrics = LinkMetrics {
+            click_depth,
+            inbound_links,
+            outbound_links,
+            page_rank: ranks.get(key.as_str()).copied().unwrap_or(0.0),
+            dead_end: outbound_links == 0 && url.response_code == Some(200),
+            orphan: inbound_links == 0 && key != seed && known.contains(&normalize(key)),
+        };
+
+        match click_depth {
+            Some(depth) => {
+                *summary.click_depth_histogram.entry(depth).or_insert(0) += 1;
+                summary.max_click_depth = summary.max_click_depth.max(depth);
+            }
+            None => summary.unreachable.push(key.clone()),
+        }
+        if metrics.dead_end {
+            summary.dead_ends.push(key.clone());
+        }
+        if metrics.orphan {
+            summary.orphans.push(key.clone());
+        }
+        url.link_metrics = Some(metrics);
+    }
+
+    let crawled: BTreeSet<String> = index.keys().map(|url| normalize(url)).collect();
+    for url in known_urls {
+        if !crawled.contains(&normalize(url)) {
+            summary.orphans.push(url.clone());
+        }
+    }
+
+    let mut ranked: Vec<RankedPage> = ranks
+        .iter()
+        .map(|(url, page_rank)| RankedPage {
+            url: url.to_string(),
+            page_rank: *page_rank,
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.page_rank.total_cmp(&a.page_rank).then_with(|| a.url.cmp(&b.url)));
+    ranked.truncate(TOP_PAGES);
+    summary.top_page_rank = ranked;
+
+    summary.unreachable.sort();
+    summary.dead_ends.sort();
+    summary.orphans.sort();
+    summary.orphans.dedup();
+    summary
+}
+
+/// Adds click depth and link counts to the crawl at `input_path`, writes it back and adds the summary to the report
+pub fn analyze_crawl(input_path: &str, seed: &str, url_list: Option<&str>) -> Result<(), WebScrapingError> {
+    let mut index = read_from_file(input_path)?;
+    let known_urls = match url_list {
+        Some(path) => read_url_list(path)?,
+        None => Vec::new(),
+    };
+
+    let summary = analyze_links(&mut index, seed, &known_urls);
+    println!(
+        "{} pages, max click depth {}, {} dead ends, {} orphans",
+        summary.pages,
+        summary.max_click_depth,
+        summary.dead_ends.len(),
+        summary.orphans.len()
+    );
+
+    write_to_file(index, input_path)?;
+    update_report(|report: &mut Report| report.link_analytics = Some(summary))
+}
+
+/// Reads the known urls of a site from a sitemap.xml or a file with one url per line
+pub fn read_url_list(path: &str) -> Result<Vec<String>, WebScrapingError> {
+    if let Ok(contents) = fs::read_to_string(Path::new(path)) {
+        Ok(parse_url_list(&contents))
+    } else {
+        println!("Trouble Opening File!");
+        Err(WebScrapingError::ReadingFromFileError)
+    }
+}
+
+fn parse_url_list(contents: &str) -> Vec<String> {
+    if contents.contains("<loc>") {
+        contents
+            .split("<loc>")
+            .skip(1)
+            .filter_map(|chunk| chunk.split("</loc>").next())
+            .map(|url| url.trim().replace("&amp;", "&"))
+            .filter(|url| !url.is_empty())
+            .collect()
+    } else {
+        contents
+            .lines()
+            .filter_map(|line| line.split(',').next())
+            .map(|url| url.trim().to_string())
+            .filter(|url| url.starts_with("http"))
+            .collect()
+    }
+}
+
+/// Links between pages of the index. References are the page the browser ended up on,
+/// so they are mapped back to the url that redirected there.
+fn internal_edges(index: &HashMap<String, Url>) -> BTreeSet<(String, String)> {
+    let mut resolve: HashMap<&str, &str> = HashMap::new();
+    for (key, url) in index.iter() {
+        if let Some(destination) = &url.redirected_to {
+            resolve.entry(destination.as_str()).or_insert(key.as_str());
+        }
+    }
+    for key in index.keys() {
+        resolve.insert(key.as_str(), key.as_str());
+    }
+
+    let mut edges = BTreeSet::new();
+    for (key, url) in index.iter() {
+        for reference in url.site_references.iter() {
+            if let Some(source) = resolve.get(reference.as_str()) {
+                if *source != key {
+                    edges.insert((source.to_string(), key.clone()));
+                }
+            }
+        }
+    }
+    edges
+}
+
+fn click_depths<'a>(seed: &'a str, outbound: &HashMap<&'a str, BTreeSet<&'a str>>) -> HashMap<&'a str, usize> {
+    let mut depths: HashMap<&str, usize> = HashMap::from([(seed, 0)]);
+    let mut queue: VecDeque<&str> = VecDeque::from([seed]);
+
+    while let Some(page) = queue.pop_front() {
+        let depth = depths[page];
+        for next in outbound.get(page).into_iter().flatten() {
+            if !depths.contains_key(next) {
+                depths.insert(next, depth + 1);
+                queue.push_back(next);
+            }
+        }
+    }
+    depths
+}
+
+/// Internal PageRank. Pages without outbound links spread their rank evenly over every page.
+fn page_rank<'a>(pages: &'a [String], outbound: &HashMap<&'a str, BTreeSet<&'a str>>) -> HashMap<&'a str, f64> {
+    let count = pages.len() as f64;
+    let mut ranks: HashMap<&str, f64> = pages.iter().map(|page| (page.as_str(), 1.0 / count)).collect();
+
+    for _ in 0..MAX_ITERATIONS {
+        let dangling: f64 = pages
+            .iter()
+            .filter(|page| outbound.get(page.as_str()).is_none_or(BTreeSet::is_empty))
+            .map(|page| ranks[page.as_str()])
+            .sum();
+        let base = (1.0 - DAMPING_FACTOR) / count + DAMPING_FACTOR * dangling / count;
+        let mut next: HashMap<&str, f64> = pages.iter().map(|page| (page.as_str(), base)).collect();
+
+        for (source, targets) in outbound.iter() {
+            let share = DAMPING_FACTOR * ranks[source] / targets.len() as f64;
+            for target in targets {
+                if let Some(rank) = next.get_mut(target) {
+                    *rank += share;
+                }
+            }
+        }
+
+        let change: f64 = pages.iter().map(|page| (next[page.as_str()] - ranks[page.as_str()]).abs()).sum();
+        ranks = next;
+        if change < CONVERGENCE {
+            break;
+        }
+    }
+    ranks
+}
+
+fn normalize(url: &str) -> String {
+    url.trim_end_matches('/').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(full_path: &str, response_code: Option<u16>, site_references: Vec<&str>) -> (String, Url) {
+        (
+            full_path.to_string(),
+            Url {
+                full_path: full_path.to_string(),
+                response_code,
+                site_references: site_references.into_iter().map(String::from).collect(),
+                ..Default::default()
+            },
+        )
+    }
+
+    fn test_index() -> HashMap<String, Url> {
+        HashMap::from([
+            url("https://example.com", Some(200), vec!["https://example.com", "https://example.com/about"]),
+            url("https://example.com/about", Some(200), vec!["https://example.com"]),
+            url("https://example.com/blog", Some(300), vec!["https://example.com"]),
+            url("https://example.com/blog/post", Some(200), vec!["https://example.com/blog/"]),
+            url("https://example.com/contact", Some(200), vec!["https://example.com/blog/post"]),
+        ])
+    }
+
+    #[test]
+    fn analyze_links_test() {
+        let mut index = test_index();
+        index.get_mut("https://example.com/blog").unwrap().redirected_to = Some("https://example.com/blog/".to_string());
+        let known = vec!["https://example.com/about/".to_string(), "https://example.com/hidden".to_string()];
+
+        let summary = analyze_links(&mut index, "https://example.com", &known);
+
+        let contact = index["https://example.com/contact"].link_metrics.clone().unwrap();
+        assert_eq!(contact.click_depth, Some(3));
+        assert_eq!(contact.inbound_links, 1);
+        assert_eq!(contact.outbound_links, 0);
+        assert!(contact.dead_end);
+
+        let home = index["https://example.com"].link_metrics.clone().unwrap();
+        assert_eq!(home.click_depth, Some(0));
+        assert_eq!(home.inbound_links, 1);
+        assert_eq!(home.outbound_links, 2);
+
+        assert_eq!(summary.max_click_depth, 3);
+        assert_eq!(summary.dead_ends, vec!["https://example.com/contact".to_string()]);
+        assert_eq!(summary.orphans, vec!["https://example.com/hidden".to_string()]);
+        assert!(summary.unreachable.is_empty());
+    }
+
+    #[test]
+    fn page_rank_sums_to_one_test() {
+        let mut index = test_index();
+        analyze_links(&mut index, "https://example.com", &[]);
+
+        let total: f64 = index.values().map(|url| url.link_metrics.as_ref().unwrap().page_rank).sum();
+        assert!((total - 1.0).abs() < 1e-6);
+
+        let home = index["https://example.com"].link_metrics.as_ref().unwrap().page_rank;
+        let about = index["https://example.com/about"].link_metrics.as_ref().unwrap().page_rank;
+        assert!(home > about);
+    }
+
+    #[test]
+    fn parse_url_list_test() {
+        let sitemap = "<urlset><url><loc>https://example.com/</loc></url>\n<url><loc> https://example.com/a?b=1&amp;c=2 </loc></url></urlset>";
+        assert_eq!(
+            parse_url_list(sitemap),
+            vec!["https://example.com/".to_string(), "https://example.com/a?b=1&c=2".to_string()]
+        );
+
+        let list = "url\nhttps://example.com/a,2022-01-01\nhttps://example.com/b\n";
+        assert_eq!(
+            parse_url_list(list),
+            vec!["https://example.com/a".to_string(), "https://example.com/b".to_string()]
+        );
+    }
+}
@@ -0,0 +1,505 @@
+use crate::webscraper::find_urls::{read_from_file, Url, WebScrapingError};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum GraphFormat {
+    Dot,
+    Gexf,
+    GraphMl,
+}
+
+impl GraphFormat {
+    pub fn from_name(name: &str) -> Option<GraphFormat> {
+        match name.to_lowercase().as_str() {
+            "dot" => Some(GraphFormat::Dot),
+            "gexf" => Some(GraphFormat::Gexf),
+            "graphml" => Some(GraphFormat::GraphMl),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            GraphFormat::Dot => "dot",
+            GraphFormat::Gexf => "gexf",
+            GraphFormat::GraphMl => "graphml",
+        }
+    }
+}
+
+/// How pages are grouped into nodes before the graph is written
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Collapse {
+    None,
+    Host,
+    /// Keeps the host plus the first `n` path segments
+    PathPrefix(usize),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct GraphOptions {
+    pub collapse: Collapse,
+    pub color_by_status: bool,
+    /// Only keep nodes within `depth` links (either direction) of this url
+    pub around: Option<String>,
+    pub depth: usize,
+}
+
+impl Default for GraphOptions {
+    fn default() -> Self {
+        GraphOptions {
+            collapse: Collapse::None,
+            color_by_status: false,
+            around: None,
+            depth: 1,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Node {
+    pub id: String,
+    pub response_code: Option<u16>,
+    pub page_count: usize,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct LinkGraph {
+    pub nodes: BTreeMap<String, Node>,
+    /// (source, target) -> number of links between them
+    pub edges: BTreeMap<(String, String), usize>,
+}
+
+impl LinkGraph {
+    /// Builds a directed graph where every site reference is an edge from the
+    /// referring page to the page it links to.
+    pub fn from_index(index: &HashMap<String, Url>) -> LinkGraph {
+        let mut graph = LinkGraph::default();
+
+        for url in index.values() {
+            graph.add_node(&url.full_path, url.response_code);
+            for reference in url.site_references.iter() {
+                if reference == &url.full_path {
+                    continue;
+                }
+                let response_code = index.get(reference).and_then(|u| u.response_code);
+                graph.add_node(reference, response_code);
+                *graph
+                    .edges
+                    .entry((reference.clone(), url.full_path.clone()))
+                    .or_insert(0) += 1;
+            }
+        }
+        graph
+    }
+
+    fn add_node(&mut self, id: &str, response_code: Option<u16>) {
+        let node = self.nodes.entry(id.to_string()).or_insert(Node {
+            id: id.to_string(),
+            response_code: None,
+            page_count: 1,
+        });
+        node.response_code = worst_status(node.response_code, response_code);
+    }
+
+    /// Merges nodes that share the same collapse key. Links inside a group are dropped.
+    pub fn collapse(self, collapse: Collapse) -> LinkGraph {
+        if collapse == Collapse::None {
+            return self;
+        }
+        let mut graph = LinkGraph::default();
+
+        for node in self.nodes.values() {
+            let key = collapse_key(&node.id, collapse);
+            let group = graph.nodes.entry(key.clone()).or_insert(Node {
+                id: key,
+                response_code: None,
+                page_count: 0,
+            });
+            group.page_count += node.page_count;
+            group.response_code = worst_status(group.response_code, node.response_code);
+        }
+
+        for ((source, target), weight) in self.edges {
+            let source = collapse_key(&source, collapse);
+            let target = collapse_key(&target, collapse);
+            if source == target {
+                continue;
+            }
+            *graph.edges.entry((source, target)).or_insert(0) += weight;
+        }
+        graph
+    }
+
+    /// Keeps only the nodes reachable within `depth` hops of `center`, ignoring edge direction.
+    pub fn subgraph(self, center: &str, depth: usize) -> LinkGraph {
+        let mut neighbours: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (source, target) in self.edges.keys() {
+            neighbours.entry(source).or_default().push(target);
+            neighbours.entry(target).or_default().push(source);
+        }
+
+        let mut keep: BTreeSet<String> = BTreeSet::new();
+        let mut queue: VecDeque<(&str, usize)> = VecDeque::new();
+        if self.nodes.contains_key(center) {
+            keep.insert(center.to_string());
+            queue.push_back((center, 0));
+        }
+        while let Some((id, distance)) = queue.pop_front() {
+            if distance >= depth {
+                continue;
+            }
+            for next in neighbours.get(id).into_iter().flatten() {
+                if keep.insert(next.to_string()) {
+                    queue.push_back((next, distance + 1));
+                }
+            }
+        }
+
+        let edges = self
+            .edges
+            .iter()
+            .filter(|((source, target), _)| keep.contains(source) && keep.contains(target))
+            .map(|(edge, weight)| (edge.clone(), *weight))
+            .collect();
+        let nodes = self
+            .nodes
+            .into_iter()
+            .filter(|(id, _)| keep.contains(id))
+            .collect();
+        LinkGraph { nodes, edges }
+    }
+
+    pub fn to_dot(&self, color_by_status: bool) -> String {
+        let mut out = String::from("digraph site {\n    node [shape=box, style=filled, fillcolor=\"#ffffff\"];\n");
+        for node in self.nodes.values() {
+            let mut attributes = format!("label=\"{}\"", escape_dot(&node_label(node)));
+            if color_by_status {
+                attributes.push_str(&format!(", fillcolor=\"{}\"", status_color(node.response_code)));
+            }
+            out.push_str(&format!("    \"{}\" [{}];\n", escape_dot(&node.id), attributes));
+        }
+        for ((source, target), weight) in self.edges.iter() {
+            out.push_str(&format!(
+                "    \"{}\" -> \"{}\" [weight={}];\n",
+                escape_dot(source),
+                escape_dot(target),
+                weight
+            ));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn to_gexf(&self, color_by_status: bool) -> String {
+        let mut out = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <gexf xmlns=\"http://gexf.net/1.3\" xmlns:viz=\"http://gexf.net/1.3/viz\" version=\"1.3\">\n\
+             \x20 <graph defaultedgetype=\"directed\">\n\
+             \x20   <attributes class=\"node\">\n\
+             \x20     <attribute id=\"0\" title=\"response_code\" type=\"integer\"/>\n\
+             \x20     <attribute id=\"1\" title=\"page_count\" type=\"integer\"/>\n\
+             \x20   </attributes>\n\
+             \x20   <nodes>\n",
+        );
+        for node in self.nodes.values() {
+            out.push_str(&format!(
+                "      <node id=\"{}\" label=\"{}\">\n        <attvalues>\n",
+                escape_xml(&node.id),
+                escape_xml(&node_label(node))
+            ));
+            if let Some(code) = node.response_code {
+                out.push_str(&format!("          <attvalue for=\"0\" value=\"{}\"/>\n", code));
+            }
+            out.push_str(&format!(
+                "          <attvalue for=\"1\" value=\"{}\"/>\n        </attvalues>\n",
+                node.page_count
+            ));
+            if color_by_status {
+                let (r, g, b) = hex_to_rgb(status_color(node.response_code));
+                out.push_str(&format!("        <viz:color r=\"{}\" g=\"{}\" b=\"{}\"/>\n", r, g, b));
+            }
+            out.push_str("      </node>\n");
+        }
+        out.push_str("    </nodes>\n    <edges>\n");
+        for (id, ((source, target), weight)) in self.edges.iter().enumerate() {
+            out.push_str(&format!(
+                "      <edge id=\"{}\" source=\"{}\" target=\"{}\" weight=\"{}\"/>\n",
+                id,
+                escape_xml(source),
+                escape_xml(target),
+                weight
+            ));
+        }
+        out.push_str("    </edges>\n  </graph>\n</gexf>\n");
+        out
+    }
+
+    pub fn to_graphml(&self, color_by_status: bool) -> String {
+        let mut out = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n\
+             \x20 <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n\
+             \x20 <key id=\"response_code\" for=\"node\" attr.name=\"response_code\" attr.type=\"int\"/>\n\
+             \x20 <key id=\"page_count\" for=\"node\" attr.name=\"page_count\" attr.type=\"int\"/>\n\
+             \x20 <key id=\"color\" for=\"node\" attr.name=\"color\" attr.type=\"string\"/>\n\
+             \x20 <key id=\"weight\" for=\"edge\" attr.name=\"weight\" attr.type=\"int\"/>\n\
+             \x20 <graph id=\"site\" edgedefault=\"directed\">\n",
+        );
+        for node in self.nodes.values() {
+            out.push_str(&format!(
+                "    <node id=\"{}\">\n      <data key=\"label\">{}</data>\n",
+                escape_xml(&node.id),
+                escape_xml(&node_label(node))
+            ));
+            if let Some(code) = node.response_code {
+                out.push_str(&format!("      <data key=\"response_code\">{}</data>\n", code));
+            }
+            out.push_str(&format!("      <data key=\"page_count\">{}</data>\n", node.page_count));
+            if color_by_status {
+                out.push_str(&format!(
+                    "      <data key=\"color\">{}</data>\n",
+                    status_color(node.response_code)
+                ));
+            }
+            out.push_str("    </node>\n");
+        }
+        for ((source, target), weight) in self.edges.iter() {
+            out.push_str(&format!(
+                "    <edge source=\"{}\" target=\"{}\">\n      <data key=\"weight\">{}</data>\n    </edge>\n",
+                escape_xml(source),
+                escape_xml(target),
+                weight
+            ));
+        }
+        out.push_str("  </graph>\n</graphml>\n");
+        out
+    }
+
+    pub fn render(&self, format: GraphFormat, color_by_status: bool) -> String {
+        match format {
+            GraphFormat::Dot => self.to_dot(color_by_status),
+            GraphFormat::Gexf => self.to_gexf(color_by_status),
+            GraphFormat::GraphMl => self.to_graphml(color_by_status),
+        }
+    }
+}
+
+/// Reads the crawl at `input_path` and writes its link graph, or the part around `options.around`, to `output_path`
+pub fn export_graph(
+    input_path: &str,
+    output_path: &str,
+    format: GraphFormat,
+    options: GraphOptions,
+) -> Result<(), WebScrapingError> {
+    let index = read_from_file(input_path)?;
+
+    let mut graph = LinkGraph::from_index(&index);
+    if let Some(center) = &options.around {
+        graph = graph.subgraph(center, options.depth);
+        if graph.nodes.is_empty() {
+            println!("{} was not found in the crawl result", center);
+        }
+    }
+    graph = graph.collapse(options.collapse);
+
+    println!(
+        "Writing graph with {} nodes and {} edges to {}",
+        graph.nodes.len(),
+        graph.edges.len(),
+        output_path
+    );
+    if let Some(parent) = Path::new(output_path).parent() {
+        if fs::DirBuilder::new().recursive(true).create(parent).is_err() {
+            println!("Trouble creating output directory!");
+            return Err(WebScrapingError::WritingToFileError);
+        }
+    }
+    if fs::write(output_path, graph.render(format, options.color_by_status)).is_err() {
+        println!("Trouble writing data!");
+        return Err(WebScrapingError::WritingToFileError);
+    }
+    Ok(())
+}
+
+fn collapse_key(url: &str, collapse: Collapse) -> String {
+    let (scheme, rest) = match url.find("://") {
+        Some(idx) => url.split_at(idx + 3),
+        None => ("", url),
+    };
+    let rest = rest.split(['?', '#']).next().unwrap_or("");
+    let mut segments = rest.split('/').filter(|segment| !segment.is_empty());
+    let host = segments.next().unwrap_or("");
+
+    match collapse {
+        Collapse::None => url.to_string(),
+        Collapse::Host => format!("{}{}", scheme, host),
+        Collapse::PathPrefix(depth) => {
+            let mut key = format!("{}{}", scheme, host);
+            for segment in segments.take(depth) {
+                key.push('/');
+                key.push_str(segment);
+            }
+            key
+        }
+    }
+}
+
+/// Keeps whichever status is most severe so a collapsed group with a 404 shows as broken
+fn worst_status(a: Option<u16>, b: Option<u16>) -> Option<u16> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+fn status_color(response_code: Option<u16>) -> &'static str {
+    match response_code {
+        Some(200..=299) => "#81c784",
+        Some(300..=399) => "#ffd54f",
+        Some(400..=599) => "#e57373",
+        _ => "#e0e0e0",
+    }
+}
+
+fn hex_to_rgb(hex: &str) -> (u8, u8, u8) {
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0);
+    (channel(1), channel(3), channel(5))
+}
+
+fn node_label(node: &Node) -> String {
+    if node.page_count > 1 {
+        format!("{} ({} pages)", node.id, node.page_count)
+    } else {
+        node.id.clone()
+    }
+}
+
+fn escape_dot(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+pub fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(full_path: &str, response_code: Option<u16>, site_references: Vec<&str>) -> Url {
+        Url {
+            full_path: full_path.to_string(),
+            response_code,
+            site_references: site_references.into_iter().map(String::from).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn test_index() -> HashMap<String, Url> {
+        let urls = vec![
+            url("https://example.com", Some(200), vec!["https://example.com"]),
+            url("https://example.com/blog", Some(200), vec!["https://example.com"]),
+            url(
+                "https://example.com/blog/post-1",
+                Some(200),
+                vec!["https://example.com/blog", "https://example.com/blog"],
+            ),
+            url("https://example.com/blog/old", Some(404), vec!["https://example.com/blog/post-1"]),
+            url("https://shop.example.com/cart", Some(200), vec!["https://example.com"]),
+        ];
+        urls.into_iter().map(|u| (u.full_path.clone(), u)).collect()
+    }
+
+    #[test]
+    fn from_index_test() {
+        let graph = LinkGraph::from_index(&test_index());
+
+        assert_eq!(graph.nodes.len(), 5);
+        assert_eq!(graph.edges.len(), 4);
+        assert_eq!(
+            graph.edges.get(&(
+                "https://example.com/blog".to_string(),
+                "https://example.com/blog/post-1".to_string()
+            )),
+            Some(&2)
+        );
+    }
+
+    #[test]
+    fn collapse_key_test() {
+        let url = "https://example.com/blog/2022/post?page=2";
+        assert_eq!(collapse_key(url, Collapse::Host), "https://example.com");
+        assert_eq!(collapse_key(url, Collapse::PathPrefix(1)), "https://example.com/blog");
+        assert_eq!(collapse_key(url, Collapse::PathPrefix(2)), "https://example.com/blog/2022");
+    }
+
+    #[test]
+    fn collapse_path_prefix_test() {
+        let graph = LinkGraph::from_index(&test_index()).collapse(Collapse::PathPrefix(1));
+
+        let blog = graph.nodes.get("https://example.com/blog").unwrap();
+        assert_eq!(blog.page_count, 3);
+        assert_eq!(blog.response_code, Some(404));
+        assert_eq!(
+            graph.edges.get(&("https://example.com".to_string(), "https://example.com/blog".to_string())),
+            Some(&1)
+        );
+        assert_eq!(graph.edges.len(), 2);
+    }
+
+    #[test]
+    fn subgraph_test() {
+        let graph = LinkGraph::from_index(&test_index()).subgraph("https://example.com/blog/post-1", 1);
+
+        let ids: Vec<&String> = graph.nodes.keys().collect();
+        assert_eq!(
+            ids,
+            vec![
+                "https://example.com/blog",
+                "https://example.com/blog/old",
+                "https://example.com/blog/post-1"
+            ]
+        );
+        assert_eq!(graph.edges.len(), 2);
+    }
+
+    #[test]
+    fn to_dot_test() {
+        let index: HashMap<String, Url> = vec![
+            url("https://example.com", Some(200), vec!["https://example.com"]),
+            url("https://example.com/a\"b", Some(404), vec!["https://example.com"]),
+        ]
+        .into_iter()
+        .map(|u| (u.full_path.clone(), u))
+        .collect();
+
+        assert_eq!(
+            LinkGraph::from_index(&index).to_dot(true),
+            "digraph site {\n    node [shape=box, style=filled, fillcolor=\"#ffffff\"];\n    \
+             \"https://example.com\" [label=\"https://example.com\", fillcolor=\"#81c784\"];\n    \
+             \"https://example.com/a\\\"b\" [label=\"https://example.com/a\\\"b\", fillcolor=\"#e57373\"];\n    \
+             \"https://example.com\" -> \"https://example.com/a\\\"b\" [weight=1];\n}\n"
+        );
+    }
+
+    #[test]
+    fn to_graphml_escapes_test() {
+        let index: HashMap<String, Url> = vec![url("https://example.com/?a=1&b=2", None, vec!["https://example.com"])]
+            .into_iter()
+            .map(|u| (u.full_path.clone(), u))
+            .collect();
+
+        let graphml = LinkGraph::from_index(&index).to_graphml(false);
+        assert!(graphml.contains("<node id=\"https://example.com/?a=1&amp;b=2\">"));
+        assert!(graphml.contains("<edge source=\"https://example.com\" target=\"https://example.com/?a=1&amp;b=2\">"));
+    }
+}
@@ -0,0 +1,2 @@
+pub mod analytics;
+pub mod export_graph;
@@ -1,7 +1,15 @@
-mod webdriver;
-mod webscraper;
-use clap::{crate_authors, crate_description, Arg, Command};
-use webscraper::find_urls::{index_urls, WebScrapingError};
+use clap::{crate_authors, crate_description, Arg, ArgMatches, Command};
+use web_audit::audit::lighthouse::audit_lighthouse;
+use web_audit::config::load_config;
+use web_audit::linkgraph::analytics::{analyze_crawl, read_url_list};
+use web_audit::linkgraph::export_graph::{export_graph, Collapse, GraphFormat, GraphOptions};
+use web_audit::redirects::suggest_redirects::{generate_redirect_rules, RuleFormat};
+use web_audit::redirects::verify_redirects::verify_redirects;
+use web_audit::sitemap::generate_sitemap::{generate_sitemap, SitemapOptions};
+use web_audit::webdriver::capabilities::{BrowserOptions, WindowSize};
+use web_audit::webdriver::webdriver::WebDriver;
+use web_audit::webscraper::find_urls::{index_urls, WebScrapingError};
+use web_audit::webscraper::page_load::WaitStrategy;
 
 #[tokio::main]
 async fn main() -> Result<(), WebScrapingError> {
@@ -15,6 +23,14 @@ async fn main() -> Result<(), WebScrapingError> {
         .author(crate_authors!("\n"))
         .version("0.0.0")
         .about(crate_description!())
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .short('c')
+                .takes_value(true)
+                .global(true)
+                .help("A TOML config file. Command line flags override its values")
+        )
         .subcommand(
             Command::new("index-urls")
                 .arg(
@@ -39,6 +55,245 @@ async fn main() -> Result<(), WebScrapingError> {
                         .help("The title of your 404 page. <title>Page Not Found</title> = 'Page Not Found'")
                         .long_help("The webscraper checks to see if the page is a 404 by checking the page title element. Make sure this title is unique to your 404 page for best results. If you don't know your 404 page title go to https://your-web-domain.com/lajdfjadsjl and inspect the page. (right click inspect). In the console type 'document.querySelector('title') It will output your title element. The value passed in only needs to contain part of the title"),
                 )
+                .arg(url_list_arg())
+                .arg(
+                    Arg::new("driver")
+                        .long("driver")
+                        .takes_value(true)
+                        .possible_values(["gecko", "chrome", "edge", "remote"])
+                        .default_value("gecko")
+                        .help("The webdriver used to load pages")
+                        .long_help("The webdriver used to load pages. gecko, chrome and edge start geckodriver, chromedriver or msedgedriver from your PATH. remote connects to an already running Selenium server given by --remote-url")
+                )
+                .arg(
+                    Arg::new("remote-url")
+                        .long("remote-url")
+                        .takes_value(true)
+                        .help("Url of the running webdriver server when --driver remote is used. Example: http://localhost:4444/wd/hub")
+                )
+                .args(browser_args())
+                .arg(
+                    Arg::new("recycle-after")
+                        .long("recycle-after")
+                        .takes_value(true)
+                        .help("Restart the browser every N pages to keep its memory use down")
+                )
+                .arg(
+                    Arg::new("page-load-timeout")
+                        .long("page-load-timeout")
+                        .takes_value(true)
+                        .help("Milliseconds to wait for a page to load before scraping what has rendered")
+                )
+                .arg(
+                    Arg::new("wait")
+                        .long("wait")
+                        .takes_value(true)
+                        .help("What to wait for before scraping links: load, network-idle, selector=<css> or delay=<ms>")
+                )
+                .arg(
+                    Arg::new("a11y")
+                        .long("a11y")
+                        .help("Run the axe-core accessibility audit on every page")
+                )
+        )
+        .subcommand(
+            Command::new("analyze-links")
+                .about("Computes click depth, link counts, PageRank, dead ends and orphans for a crawl result")
+                .arg(
+                    Arg::new("input")
+                        .long("input")
+                        .short('i')
+                        .takes_value(true)
+                        .default_value("./data/all_urls.json")
+                        .help("The crawl result written by index-urls")
+                )
+                .arg(
+                    Arg::new("starting-url")
+                        .long("url")
+                        .short('u')
+                        .takes_value(true)
+                        .required(true)
+                        .help("The seed url click depth is measured from")
+                )
+                .arg(url_list_arg())
+        )
+        .subcommand(
+            Command::new("export-graph")
+                .about("Exports the link graph of a crawl result")
+                .arg(
+                    Arg::new("input")
+                        .long("input")
+                        .short('i')
+                        .takes_value(true)
+                        .default_value("./data/all_urls.json")
+                        .help("The crawl result written by index-urls")
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .takes_value(true)
+                        .help("Where to write the graph. Defaults to ./data/link_graph.<format>")
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .short('f')
+                        .takes_value(true)
+                        .possible_values(["dot", "gexf", "graphml"])
+                        .default_value("dot")
+                        .help("The graph file format")
+                )
+                .arg(
+                    Arg::new("collapse")
+                        .long("collapse")
+                        .takes_value(true)
+                        .possible_values(["none", "host", "prefix"])
+                        .default_value("none")
+                        .help("Group pages into a single node by host or by path prefix")
+                )
+                .arg(
+                    Arg::new("prefix-depth")
+                        .long("prefix-depth")
+                        .takes_value(true)
+                        .default_value("1")
+                        .help("Number of path segments kept when collapsing by prefix")
+                )
+                .arg(
+                    Arg::new("color-by-status")
+                        .long("color-by-status")
+                        .help("Color nodes by their response code")
+                )
+                .arg(
+                    Arg::new("around")
+                        .long("around")
+                        .takes_value(true)
+                        .help("Only export the pages within --depth links of this url")
+                )
+                .arg(
+                    Arg::new("depth")
+                        .long("depth")
+                        .takes_value(true)
+                        .default_value("1")
+                        .help("Number of links to follow from --around")
+                )
+        )
+        .subcommand(
+            Command::new("sitemap")
+                .about("Generates a sitemap.xml from the canonical, indexable 200 pages of a crawl result")
+                .arg(
+                    Arg::new("input")
+                        .long("input")
+                        .short('i')
+                        .takes_value(true)
+                        .default_value("./data/all_urls.json")
+                        .help("The crawl result written by index-urls")
+                )
+                .arg(
+                    Arg::new("output-dir")
+                        .long("output-dir")
+                        .short('o')
+                        .takes_value(true)
+                        .default_value("./data")
+                        .help("Directory the sitemap files are written to")
+                )
+                .arg(
+                    Arg::new("base-url")
+                        .long("base-url")
+                        .takes_value(true)
+                        .help("Where the sitemap files will be hosted. Needed when the sitemap is split and a sitemap index is written")
+                )
+                .arg(
+                    Arg::new("lastmod")
+                        .long("lastmod")
+                        .help("Request every page and use its Last-Modified header as lastmod")
+                )
+                .arg(
+                    Arg::new("images")
+                        .long("images")
+                        .help("List the images of every page")
+                )
+        )
+        .subcommand(
+            Command::new("audit")
+                .about("Runs external audit tools on the pages of a crawl result")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("lighthouse")
+                        .about("Runs Lighthouse on every 200 page and stores the category scores and failed audits")
+                        .arg(
+                            Arg::new("input")
+                                .long("input")
+                                .short('i')
+                                .takes_value(true)
+                                .default_value("./data/all_urls.json")
+                                .help("The crawl result written by index-urls")
+                        )
+                        .arg(
+                            Arg::new("command")
+                                .long("command")
+                                .takes_value(true)
+                                .help("The Lighthouse command, e.g. \"npx lighthouse\". The url and the configured args are appended")
+                        )
+                        .arg(
+                            Arg::new("concurrency")
+                                .long("concurrency")
+                                .takes_value(true)
+                                .help("Number of Lighthouse runs at the same time. Defaults to 2")
+                        )
+                        .arg(
+                            Arg::new("timeout")
+                                .long("timeout")
+                                .takes_value(true)
+                                .help("Seconds after which a run is killed. Defaults to 120")
+                        )
+                )
+        )
+        .subcommand(
+            Command::new("verify-redirects")
+                .about("Checks that old urls permanently redirect to their new urls in a single hop")
+                .arg(
+                    Arg::new("csv")
+                        .required(true)
+                        .takes_value(true)
+                        .help("A csv file of old_url,expected_new_url pairs")
+                )
+        )
+        .subcommand(
+            Command::new("suggest-redirects")
+                .about("Suggests a live page for every internal 404 and writes redirect rules")
+                .arg(
+                    Arg::new("input")
+                        .long("input")
+                        .short('i')
+                        .takes_value(true)
+                        .default_value("./data/all_urls.json")
+                        .help("The crawl result written by index-urls")
+                )
+                .arg(
+                    Arg::new("output-dir")
+                        .long("output-dir")
+                        .short('o')
+                        .takes_value(true)
+                        .default_value("./data/redirects")
+                        .help("Directory the rule files are written to")
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .short('f')
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .possible_values(["nginx", "apache", "netlify", "csv"])
+                        .help("Rule formats to write. Defaults to all of them")
+                )
+                .arg(
+                    Arg::new("min-confidence")
+                        .long("min-confidence")
+                        .takes_value(true)
+                        .default_value("0.5")
+                        .help("Only write rules for suggestions with at least this confidence (0 to 1). The csv always lists every suggestion")
+                )
         )
         .get_matches();
 
@@ -57,7 +312,7 @@ async fn main() -> Result<(), WebScrapingError> {
             //TODO: convert file path to vec. 
             domains = vec![domains_.to_string()];
         } else {
-            domains = vec![(&url).clone().to_string()]
+            domains = vec![url.to_string()]
         }
 
         if let Some(not_found_title_) = sub_matches.value_of("404-title") {
@@ -66,12 +321,211 @@ async fn main() -> Result<(), WebScrapingError> {
             not_found_title = "Page Not Found";
         }
         
+        let known_urls = match sub_matches.value_of("url-list") {
+            Some(path) => read_url_list(path)?,
+            None => Vec::new(),
+        };
+
+        let driver = match WebDriver::from_name(
+            sub_matches.value_of("driver").unwrap_or("gecko"),
+            sub_matches.value_of("remote-url"),
+        ) {
+            Some(driver) => driver,
+            None => panic!("--remote-url must be provided with --driver remote"),
+        };
+
+        let mut config = load_config(matches.value_of("config"))?;
+        config.browser = browser_options(sub_matches, config.browser);
+        if sub_matches.is_present("recycle-after") {
+            config.crawl.recycle_after = Some(parse_number(sub_matches.value_of("recycle-after"), "recycle-after"));
+        }
+        if sub_matches.is_present("page-load-timeout") {
+            config.crawl.page_load.timeouts.page_load_ms =
+                parse_number(sub_matches.value_of("page-load-timeout"), "page-load-timeout") as u64;
+        }
+        if let Some(wait) = sub_matches.value_of("wait") {
+            config.crawl.page_load.wait = match WaitStrategy::parse(wait) {
+                Some(strategy) => strategy,
+                None => panic!("--wait must be load, network-idle, selector=<css> or delay=<ms>"),
+            };
+        }
+        if sub_matches.is_present("a11y") {
+            config.a11y.enabled = true;
+        }
+
         index_urls(
             url.to_string(),
             domains,
-            not_found_title.to_string()
+            not_found_title.to_string(),
+            known_urls,
+            driver,
+            config,
         )
         .await?;
     };
+
+    if let Some(sub_matches) = matches.subcommand_matches("analyze-links") {
+        analyze_crawl(
+            sub_matches.value_of("input").unwrap_or("./data/all_urls.json"),
+            sub_matches.value_of("starting-url").unwrap_or_default(),
+            sub_matches.value_of("url-list"),
+        )?;
+    }
+
+    if let Some(sub_matches) = matches.subcommand_matches("export-graph") {
+        let format = GraphFormat::from_name(sub_matches.value_of("format").unwrap_or("dot"))
+            .unwrap_or(GraphFormat::Dot);
+        let prefix_depth = parse_number(sub_matches.value_of("prefix-depth"), "prefix-depth");
+        let collapse = match sub_matches.value_of("collapse") {
+            Some("host") => Collapse::Host,
+            Some("prefix") => Collapse::PathPrefix(prefix_depth),
+            _ => Collapse::None,
+        };
+        let output = match sub_matches.value_of("output") {
+            Some(output) => output.to_string(),
+            None => format!("./data/link_graph.{}", format.extension()),
+        };
+
+        export_graph(
+            sub_matches.value_of("input").unwrap_or("./data/all_urls.json"),
+            &output,
+            format,
+            GraphOptions {
+                collapse,
+                color_by_status: sub_matches.is_present("color-by-status"),
+                around: sub_matches.value_of("around").map(String::from),
+                depth: parse_number(sub_matches.value_of("depth"), "depth"),
+            },
+        )?;
+    }
+
+    if let Some(sub_matches) = matches.subcommand_matches("sitemap") {
+        generate_sitemap(
+            sub_matches.value_of("input").unwrap_or("./data/all_urls.json"),
+            SitemapOptions {
+                output_dir: sub_matches.value_of("output-dir").unwrap_or("./data").to_string(),
+                base_url: sub_matches.value_of("base-url").map(String::from),
+                lastmod: sub_matches.is_present("lastmod"),
+                images: sub_matches.is_present("images"),
+            },
+        )
+        .await?;
+    }
+
+    if let Some(("lighthouse", sub_matches)) = matches.subcommand_matches("audit").and_then(ArgMatches::subcommand) {
+        let mut options = load_config(matches.value_of("config"))?.lighthouse;
+        if let Some(command) = sub_matches.value_of("command") {
+            options.command = command.to_string();
+        }
+        if sub_matches.is_present("concurrency") {
+            options.concurrency = parse_number(sub_matches.value_of("concurrency"), "concurrency");
+        }
+        if sub_matches.is_present("timeout") {
+            options.timeout_secs = parse_number(sub_matches.value_of("timeout"), "timeout") as u64;
+        }
+
+        audit_lighthouse(
+            sub_matches.value_of("input").unwrap_or("./data/all_urls.json"),
+            &options,
+        )
+        .await?;
+    }
+
+    if let Some(sub_matches) = matches.subcommand_matches("verify-redirects") {
+        verify_redirects(sub_matches.value_of("csv").unwrap_or_default()).await?;
+    }
+
+    if let Some(sub_matches) = matches.subcommand_matches("suggest-redirects") {
+        let formats = match sub_matches.values_of("format") {
+            Some(names) => names.filter_map(RuleFormat::from_name).collect(),
+            None => RuleFormat::all(),
+        };
+        let min_confidence: f64 = match sub_matches.value_of("min-confidence").map(str::parse) {
+            Some(Ok(min_confidence)) => min_confidence,
+            _ => panic!("min-confidence must be a number between 0 and 1"),
+        };
+
+        generate_redirect_rules(
+            sub_matches.value_of("input").unwrap_or("./data/all_urls.json"),
+            sub_matches.value_of("output-dir").unwrap_or("./data/redirects"),
+            formats,
+            min_confidence,
+        )?;
+    }
     Ok(())
 }
+
+fn browser_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::new("headless")
+            .long("headless")
+            .help("Run the browser without a window"),
+        Arg::new("window-size")
+            .long("window-size")
+            .takes_value(true)
+            .help("Browser window size. Example: 1920x1080"),
+        Arg::new("user-agent")
+            .long("user-agent")
+            .takes_value(true)
+            .help("Override the browser's user agent"),
+        Arg::new("locale")
+            .long("locale")
+            .takes_value(true)
+            .help("Browser language and Accept-Language. Example: de-DE"),
+        Arg::new("browser-arg")
+            .long("browser-arg")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .allow_hyphen_values(true)
+            .help("Extra argument passed to the browser. Can be repeated"),
+        Arg::new("disable-images")
+            .long("disable-images")
+            .help("Don't load images. Speeds up crawls that only need links"),
+        Arg::new("disable-fonts")
+            .long("disable-fonts")
+            .help("Don't load web fonts. Speeds up crawls that only need links"),
+    ]
+}
+
+/// Applies the browser flags on top of the [browser] section of the config file
+fn browser_options(sub_matches: &ArgMatches, mut options: BrowserOptions) -> BrowserOptions {
+    if sub_matches.is_present("headless") {
+        options.headless = true;
+    }
+    if let Some(window_size) = sub_matches.value_of("window-size") {
+        match WindowSize::parse(window_size) {
+            Some(size) => options.window_size = Some(size),
+            None => panic!("window-size must look like 1920x1080"),
+        }
+    }
+    if let Some(user_agent) = sub_matches.value_of("user-agent") {
+        options.user_agent = Some(user_agent.to_string());
+    }
+    if let Some(locale) = sub_matches.value_of("locale") {
+        options.locale = Some(locale.to_string());
+    }
+    if let Some(args) = sub_matches.values_of("browser-arg") {
+        options.args.extend(args.map(String::from));
+    }
+    if sub_matches.is_present("disable-images") {
+        options.disable_images = true;
+    }
+    if sub_matches.is_present("disable-fonts") {
+        options.disable_fonts = true;
+    }
+    options
+}
+
+fn url_list_arg() -> Arg<'static> {
+    Arg::new("url-list")
+        .long("url-list")
+        .takes_value(true)
+        .help("A sitemap.xml or a file with one url per line, used to find orphan pages")
+}
+
+fn parse_number(value: Option<&str>, name: &str) -> usize {
+    match value.map(str::parse) {
+        Some(Ok(number)) => number,
+        _ => panic!("{} must be a positive number", name),
+    }
+}
@@ -0,0 +1,2 @@
+pub mod suggest_redirects;
+pub mod verify_redirects;
@@ -0,0 +1,460 @@
+use crate::webscraper::find_urls::{read_from_file, Url, WebScrapingError};
+use reqwest::Url as ParsedUrl;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+const PATH_WEIGHT: f64 = 0.25;
+const SLUG_WEIGHT: f64 = 0.4;
+const TITLE_WEIGHT: f64 = 0.35;
+const STOP_WORDS: [&str; 10] = ["a", "an", "and", "the", "of", "in", "for", "html", "htm", "php"];
+
+/// Formats written as redirect rules for a web server or host
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ServerFormat {
+    Nginx,
+    Apache,
+    Netlify,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RuleFormat {
+    Server(ServerFormat),
+    /// Every suggestion with its scores, for review by hand
+    Csv,
+}
+
+impl RuleFormat {
+    pub fn from_name(name: &str) -> Option<RuleFormat> {
+        match name.to_lowercase().as_str() {
+            "nginx" => Some(RuleFormat::Server(ServerFormat::Nginx)),
+            "apache" | "htaccess" => Some(RuleFormat::Server(ServerFormat::Apache)),
+            "netlify" => Some(RuleFormat::Server(ServerFormat::Netlify)),
+            "csv" => Some(RuleFormat::Csv),
+            _ => None,
+        }
+    }
+
+    pub fn all() -> Vec<RuleFormat> {
+        vec![
+            RuleFormat::Server(ServerFormat::Nginx),
+            RuleFormat::Server(ServerFormat::Apache),
+            RuleFormat::Server(ServerFormat::Netlify),
+            RuleFormat::Csv,
+        ]
+    }
+
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            RuleFormat::Server(ServerFormat::Nginx) => "nginx_redirects.conf",
+            RuleFormat::Server(ServerFormat::Apache) => ".htaccess",
+            RuleFormat::Server(ServerFormat::Netlify) => "_redirects",
+            RuleFormat::Csv => "redirect_suggestions.csv",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct RedirectSuggestion {
+    pub broken_url: String,
+    pub target_url: String,
+    /// Weighted score between 0 and 1
+    pub confidence: f64,
+    pub path_score: f64,
+    pub slug_score: f64,
+    pub title_score: f64,
+}
+
+/// Finds the most likely live page for every internal 404 in the index
+pub fn suggest_redirects(index: &HashMap<String, Url>) -> Vec<RedirectSuggestion> {
+    let live_pages: Vec<&Url> = index.values().filter(|url| url.response_code == Some(200)).collect();
+    let mut broken: Vec<&Url> = index.values().filter(|url| url.response_code == Some(404)).collect();
+    broken.sort_by(|a, b| a.full_path.cmp(&b.full_path));
+
+    broken
+        .into_iter()
+        .filter_map(|broken_url| {
+            live_pages
+                .iter()
+                .map(|candidate| score(broken_url, candidate))
+                .max_by(|a, b| {
+                    a.confidence
+                        .total_cmp(&b.confidence)
+                        .then_with(|| b.target_url.cmp(&a.target_url))
+                })
+        })
+        .collect()
+}
+
+fn score(broken_url: &Url, candidate: &Url) -> RedirectSuggestion {
+    let broken_path = url_path(&broken_url.full_path);
+    let candidate_path = url_path(&candidate.full_path);
+
+    let longest = broken_path.chars().count().max(candidate_path.chars().count()).max(1);
+    let path_score = 1.0 - levenshtein(&broken_path, &candidate_path) as f64 / longest as f64;
+
+    let broken_tokens = tokens(&broken_path);
+    let slug_score = jaccard(&broken_tokens, &tokens(&candidate_path));
+    // Titles describe the page itself, so only the last path segment is compared
+    let last_segment = broken_path.trim_end_matches('/').rsplit('/').next().unwrap_or_default();
+    let title_score = match &candidate.title {
+        Some(title) => jaccard(&tokens(last_segment), &tokens(title)),
+        None => 0.0,
+    };
+
+    RedirectSuggestion {
+        broken_url: broken_url.full_path.clone(),
+        target_url: candidate.full_path.clone(),
+        confidence: PATH_WEIGHT * path_score + SLUG_WEIGHT * slug_score + TITLE_WEIGHT * title_score,
+        path_score,
+        slug_score,
+        title_score,
+    }
+}
+
+/// Renders the suggestions as redirect rules. Rule formats only include suggestions at or
+/// above `min_confidence`; the csv lists every suggestion so the rest can be reviewed by hand.
+pub fn render_rules(suggestions: &[RedirectSuggestion], format: RuleFormat, min_confidence: f64) -> String {
+    match format {
+        RuleFormat::Server(server) => render_server_rules(suggestions, server, min_confidence),
+        RuleFormat::Csv => render_csv(suggestions),
+    }
+}
+
+fn render_csv(suggestions: &[RedirectSuggestion]) -> String {
+    let mut out = String::from("source,target,confidence,path_score,slug_score,title_score\n");
+    for suggestion in suggestions {
+        out.push_str(&format!(
+            "{},{},{:.3},{:.3},{:.3},{:.3}\n",
+            csv_field(&suggestion.broken_url),
+            csv_field(&suggestion.target_url),
+            suggestion.confidence,
+            suggestion.path_score,
+            suggestion.slug_score,
+            suggestion.title_score
+        ));
+    }
+    out
+}
+
+/// nginx `location` and Apache `Redirect` only look at the path, so urls with a query string are
+/// matched on the whole request uri and with mod_rewrite instead. Netlify can't match query values
+/// and skips them.
+fn render_server_rules(suggestions: &[RedirectSuggestion], server: ServerFormat, min_confidence: f64) -> String {
+    let mut out = String::new();
+    let mut rewrite_engine_on = false;
+    for suggestion in suggestions.iter().filter(|s| s.confidence >= min_confidence) {
+        let source = url_path(&suggestion.broken_url);
+        let target = relative_target(&suggestion.broken_url, &suggestion.target_url);
+        let (encoded_path, query) = match source.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (source.as_str(), None),
+        };
+        // nginx locations and Apache match the decoded path, the query string stays as sent
+        let path = percent_decode(encoded_path);
+        let path = path.as_str();
+        let rule = match (server, query) {
+            (ServerFormat::Nginx, None) => {
+                format!("location = {} {{ return 301 {}; }}", nginx_quote(path), nginx_target(&target))
+            }
+            // A location block per query would clash, the raw request uri tells them apart
+            (ServerFormat::Nginx, Some(_)) => format!(
+                "if ($request_uri = {}) {{ return 301 {}; }}",
+                nginx_quote(&source),
+                nginx_target(&target)
+            ),
+            (ServerFormat::Apache, None) => format!("Redirect 301 {} {}", apache_quote(path), apache_quote(&target)),
+            (ServerFormat::Apache, Some(query)) => {
+                let mut rule = String::new();
+                if !rewrite_engine_on {
+                    rule.push_str("RewriteEngine On\n");
+                    rewrite_engine_on = true;
+                }
+                // QSD drops the old query string unless the target brings its own
+                rule.push_str(&format!(
+                    "RewriteCond %{{QUERY_STRING}} {}\nRewriteRule {} {} [R=301,L,QSD]",
+                    apache_quote(&format!("^{}$", regex_escape(query))),
+                    apache_quote(&format!("^/?{}$", regex_escape(path.trim_start_matches('/')))),
+                    apache_quote(&target)
+                ));
+                rule
+            }
+            (ServerFormat::Netlify, None) => {
+                format!("{} {} 301", netlify_escape(encoded_path), netlify_escape(&target))
+            }
+            (ServerFormat::Netlify, Some(_)) => {
+                out.push_str(&format!(
+                    "# skipped {}, Netlify can't match query values\n",
+                    netlify_escape(&source)
+                ));
+                continue;
+            }
+        };
+        out.push_str(&format!("# confidence {:.2}\n{}\n", suggestion.confidence, rule));
+    }
+    out
+}
+
+/// Double quotes an nginx argument that would otherwise end early or start a comment
+fn nginx_quote(value: &str) -> String {
+    if value.chars().any(|c| c.is_whitespace() || "\"';{}#\\".contains(c)) {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// `return` expands variables, so a literal `$` is sent encoded
+fn nginx_target(target: &str) -> String {
+    nginx_quote(&target.replace('$', "%24"))
+}
+
+fn apache_quote(value: &str) -> String {
+    if value.chars().any(|c| c.is_whitespace() || c == '"') {
+        format!("\"{}\"", value.replace('"', "\\\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Netlify splits on whitespace and has no quoting
+fn netlify_escape(value: &str) -> String {
+    value.replace(' ', "%20").replace('\t', "%09")
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn regex_escape(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        if "\\.^$|?*+()[]{}".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Writes a rules file per format into `output_dir` with redirects for the broken urls of the crawl at `input_path`
+pub fn generate_redirect_rules(
+    input_path: &str,
+    output_dir: &str,
+    formats: Vec<RuleFormat>,
+    min_confidence: f64,
+) -> Result<(), WebScrapingError> {
+    let index = read_from_file(input_path)?;
+    let suggestions = suggest_redirects(&index);
+    let confident = suggestions.iter().filter(|s| s.confidence >= min_confidence).count();
+    println!(
+        "{} broken urls, {} suggestions at or above {} confidence",
+        suggestions.len(),
+        confident,
+        min_confidence
+    );
+
+    if fs::DirBuilder::new().recursive(true).create(output_dir).is_err() {
+        println!("Trouble creating output directory!");
+        return Err(WebScrapingError::WritingToFileError);
+    }
+    for format in formats {
+        let path = Path::new(output_dir).join(format.file_name());
+        println!("Writing {}", path.display());
+        if fs::write(&path, render_rules(&suggestions, format, min_confidence)).is_err() {
+            println!("Trouble writing data!");
+            return Err(WebScrapingError::WritingToFileError);
+        }
+    }
+    Ok(())
+}
+
+/// Path and query of a url, or the url itself if it can't be parsed
+fn url_path(url: &str) -> String {
+    match ParsedUrl::parse(url) {
+        Ok(parsed) => match parsed.query() {
+            Some(query) => format!("{}?{}", parsed.path(), query),
+            None => parsed.path().to_string(),
+        },
+        Err(_) => url.to_string(),
+    }
+}
+
+/// Same-host targets are written as paths so the rules work on any environment
+fn relative_target(source: &str, target: &str) -> String {
+    match (ParsedUrl::parse(source), ParsedUrl::parse(target)) {
+        (Ok(source), Ok(target_url)) if source.origin() == target_url.origin() => url_path(target),
+        _ => target.to_string(),
+    }
+}
+
+fn tokens(text: &str) -> BTreeSet<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty() && !STOP_WORDS.contains(token))
+        .map(String::from)
+        .collect()
+}
+
+fn jaccard(a: &BTreeSet<String>, b: &BTreeSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains(',') || value.contains('"') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(full_path: &str, response_code: u16, title: &str) -> (String, Url) {
+        (
+            full_path.to_string(),
+            Url {
+                full_path: full_path.to_string(),
+                response_code: Some(response_code),
+                title: Some(title.to_string()),
+                ..Default::default()
+            },
+        )
+    }
+
+    fn test_index() -> HashMap<String, Url> {
+        HashMap::from([
+            url("https://example.com/blog/how-to-print-pla", 404, "Page Not Found"),
+            url("https://example.com/blog/printing-pla-guide", 200, "How to Print PLA"),
+            url("https://example.com/blog/how-to-paint", 200, "Painting Miniatures"),
+            url("https://example.com/shop", 200, "Shop"),
+        ])
+    }
+
+    #[test]
+    fn levenshtein_test() {
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("/about", "/about"), 0);
+    }
+
+    #[test]
+    fn suggest_redirects_test() {
+        let suggestions = suggest_redirects(&test_index());
+
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].broken_url, "https://example.com/blog/how-to-print-pla");
+        assert_eq!(suggestions[0].target_url, "https://example.com/blog/printing-pla-guide");
+        assert_eq!(suggestions[0].title_score, 1.0);
+    }
+
+    #[test]
+    fn render_rules_test() {
+        let suggestions = vec![
+            RedirectSuggestion {
+                broken_url: "https://example.com/old".to_string(),
+                target_url: "https://example.com/new".to_string(),
+                confidence: 0.9,
+                path_score: 0.5,
+                slug_score: 1.0,
+                title_score: 1.0,
+            },
+            RedirectSuggestion {
+                broken_url: "https://example.com/gone".to_string(),
+                target_url: "https://shop.example.com/".to_string(),
+                confidence: 0.2,
+                path_score: 0.2,
+                slug_score: 0.0,
+                title_score: 0.0,
+            },
+        ];
+
+        assert_eq!(
+            render_rules(&suggestions, RuleFormat::Server(ServerFormat::Nginx), 0.5),
+            "# confidence 0.90\nlocation = /old { return 301 /new; }\n"
+        );
+        assert_eq!(
+            render_rules(&suggestions, RuleFormat::Server(ServerFormat::Apache), 0.1),
+            "# confidence 0.90\nRedirect 301 /old /new\n# confidence 0.20\nRedirect 301 /gone https://shop.example.com/\n"
+        );
+        assert_eq!(
+            render_rules(&suggestions, RuleFormat::Server(ServerFormat::Netlify), 0.5),
+            "# confidence 0.90\n/old /new 301\n"
+        );
+        assert_eq!(
+            render_rules(&suggestions, RuleFormat::Csv, 0.5).lines().count(),
+            3
+        );
+    }
+
+    #[test]
+    fn render_rules_query_and_quoting_test() {
+        let suggestion = |broken_url: &str| RedirectSuggestion {
+            broken_url: broken_url.to_string(),
+            target_url: "https://example.com/new page".to_string(),
+            confidence: 0.9,
+            path_score: 0.5,
+            slug_score: 1.0,
+            title_score: 1.0,
+        };
+        let suggestions = vec![
+            suggestion("https://example.com/old page"),
+            suggestion("https://example.com/product.php?id=7"),
+        ];
+
+        assert_eq!(
+            render_rules(&suggestions, RuleFormat::Server(ServerFormat::Nginx), 0.5),
+            "# confidence 0.90\nlocation = \"/old page\" { return 301 /new%20page; }\n\
+             # confidence 0.90\nif ($request_uri = /product.php?id=7) { return 301 /new%20page; }\n"
+        );
+        assert_eq!(
+            render_rules(&suggestions, RuleFormat::Server(ServerFormat::Apache), 0.5),
+            "# confidence 0.90\nRedirect 301 \"/old page\" /new%20page\n\
+             # confidence 0.90\nRewriteEngine On\nRewriteCond %{QUERY_STRING} ^id=7$\n\
+             RewriteRule ^/?product\\.php$ /new%20page [R=301,L,QSD]\n"
+        );
+        assert_eq!(
+            render_rules(&suggestions, RuleFormat::Server(ServerFormat::Netlify), 0.5),
+            "# confidence 0.90\n/old%20page /new%20page 301\n\
+             # skipped /product.php?id=7, Netlify can't match query values\n"
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use crate::report::{update_report, Report};
+use crate::webscraper::fetch::{no_redirect_client, redirect_chain, resolve_url};
+use crate::webscraper::find_urls::{Url, WebScrapingError};
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+const CONCURRENCY: usize = 8;
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub enum RedirectIssue {
+    /// The old url answered without redirecting
+    NotRedirected,
+    /// The chain ends somewhere other than the expected url
+    WrongTarget,
+    /// The first redirect is a 302, 303 or 307 instead of a 301 or 308
+    NotPermanent,
+    /// More than one redirect before the final page
+    Chain,
+    /// The final page is a 404 (or another error status)
+    FinalError,
+    RequestFailed,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct RedirectCheck {
+    pub old_url: String,
+    pub expected_url: String,
+    pub final_status: Option<u16>,
+    pub issues: Vec<RedirectIssue>,
+    pub url: Url,
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct RedirectSummary {
+    pub checked: usize,
+    pub passed: usize,
+    pub failed: usize,
+    /// Only the checks with at least one issue
+    pub mismatches: Vec<RedirectCheck>,
+}
+
+/// Reads `old_url,expected_new_url` pairs. A header row and quoted values are allowed.
+pub fn parse_redirect_csv(contents: &str) -> Vec<(String, String)> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut columns = line.split(',').map(|column| column.trim().trim_matches('"').to_string());
+            let old_url = columns.next()?;
+            let expected_url = columns.next()?;
+            if old_url.starts_with("http") {
+                Some((old_url, expected_url))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Compares a recorded redirect chain with the expected destination
+pub fn check_redirect(old_url: &str, expected_url: &str, url: Url) -> RedirectCheck {
+    let expected_url = resolve_url(old_url, expected_url).unwrap_or_else(|| expected_url.to_string());
+    let chain = &url.redirect_chain;
+    let mut issues = Vec::new();
+
+    let final_status = chain.last().map(|hop| hop.status);
+    match chain.first() {
+        None => issues.push(RedirectIssue::RequestFailed),
+        Some(first) if !(300..400).contains(&first.status) => issues.push(RedirectIssue::NotRedirected),
+        Some(first) => {
+            if first.status != 301 && first.status != 308 {
+                issues.push(RedirectIssue::NotPermanent);
+            }
+            if chain.len() > 2 {
+                issues.push(RedirectIssue::Chain);
+            }
+            let destination = url.redirected_to.as_deref().unwrap_or_default();
+            if normalize(destination) != normalize(&expected_url) {
+                issues.push(RedirectIssue::WrongTarget);
+            }
+        }
+    }
+    if let Some(status) = final_status {
+        if status >= 400 {
+            issues.push(RedirectIssue::FinalError);
+        }
+    }
+
+    RedirectCheck {
+        old_url: old_url.to_string(),
+        expected_url,
+        final_status,
+        issues,
+        url,
+    }
+}
+
+/// Follows every old url of the csv at `csv_path` and adds the ones not ending at their new url to the report
+pub async fn verify_redirects(csv_path: &str) -> Result<(), WebScrapingError> {
+    let pairs = match fs::read_to_string(Path::new(csv_path)) {
+        Ok(contents) => parse_redirect_csv(&contents),
+        Err(_) => {
+            println!("Trouble Opening File!");
+            return Err(WebScrapingError::ReadingFromFileError);
+        }
+    };
+    println!("Verifying {} redirects", pairs.len());
+
+    let client = no_redirect_client();
+    let checks: Vec<RedirectCheck> = stream::iter(pairs.iter())
+        .map(|(old_url, expected_url)| {
+            let client = &client;
+            async move {
+                let mut url = Url::new(old_url.clone(), None, csv_path.to_string());
+                match redirect_chain(client, old_url).await {
+                    Ok(chain) => {
+                        url.set_redirect_chain(chain);
+                    }
+                    Err(e) => println!("Request to {} failed: {}", old_url, e),
+                }
+                check_redirect(old_url, expected_url, url)
+            }
+        })
+        .buffered(CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut summary = RedirectSummary {
+        checked: checks.len(),
+        ..Default::default()
+    };
+    for check in checks {
+        if check.issues.is_empty() {
+            summary.passed += 1;
+        } else {
+            println!("{} -> {:?}", check.old_url, check.issues);
+            summary.failed += 1;
+            summary.mismatches.push(check);
+        }
+    }
+    println!("{} of {} redirects passed", summary.passed, summary.checked);
+
+    update_report(|report: &mut Report| report.redirect_verification = Some(summary))
+}
+
+fn normalize(url: &str) -> &str {
+    url.trim_end_matches('/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webscraper::fetch::RedirectHop;
+
+    fn url_with_chain(hops: Vec<(&str, u16)>) -> Url {
+        let mut url = Url::new(hops[0].0.to_string(), None, "redirects.csv".to_string());
+        url.set_redirect_chain(
+            hops.into_iter()
+                .map(|(url, status)| RedirectHop { url: url.to_string(), status })
+                .collect(),
+        );
+        url
+    }
+
+    #[test]
+    fn parse_redirect_csv_test() {
+        let csv = "old_url,expected_new_url\n\"https://old.com/a\", /a\nhttps://old.com/b,https://new.com/b\n\n";
+        assert_eq!(
+            parse_redirect_csv(csv),
+            vec![
+                ("https://old.com/a".to_string(), "/a".to_string()),
+                ("https://old.com/b".to_string(), "https://new.com/b".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_redirect_passes_test() {
+        let url = url_with_chain(vec![("https://old.com/a", 301), ("https://new.com/a/", 200)]);
+        let check = check_redirect("https://old.com/a", "https://new.com/a", url);
+        assert_eq!(check.issues, vec![]);
+        assert_eq!(check.final_status, Some(200));
+    }
+
+    #[test]
+    fn check_redirect_mismatches_test() {
+        let url = url_with_chain(vec![
+            ("https://old.com/a", 302),
+            ("https://old.com/b", 301),
+            ("https://old.com/missing", 404),
+        ]);
+        let check = check_redirect("https://old.com/a", "/a-new", url);
+        assert_eq!(check.expected_url, "https://old.com/a-new");
+        assert_eq!(
+            check.issues,
+            vec![
+                RedirectIssue::NotPermanent,
+                RedirectIssue::Chain,
+                RedirectIssue::WrongTarget,
+                RedirectIssue::FinalError
+            ]
+        );
+    }
+
+    #[test]
+    fn check_redirect_not_redirected_test() {
+        let url = url_with_chain(vec![("https://old.com/a", 200)]);
+        let check = check_redirect("https://old.com/a", "https://new.com/a", url);
+        assert_eq!(check.issues, vec![RedirectIssue::NotRedirected]);
+
+        let failed = Url::new("https://old.com/a".to_string(), None, "redirects.csv".to_string());
+        let check = check_redirect("https://old.com/a", "https://new.com/a", failed);
+        assert_eq!(check.issues, vec![RedirectIssue::RequestFailed]);
+    }
+}
@@ -0,0 +1,72 @@
+use crate::audit::a11y::A11ySummary;
+use crate::audit::canonical::CanonicalSummary;
+use crate::audit::lighthouse::LighthouseSummary;
+use crate::audit::performance::PerformanceSummary;
+use crate::audit::weight::ResourceInventory;
+use crate::audit::security::HeaderMatrix;
+use crate::audit::seo::SeoSummary;
+use crate::linkgraph::analytics::LinkSummary;
+use crate::redirects::verify_redirects::RedirectSummary;
+use crate::webscraper::console::ConsoleSummary;
+use crate::webscraper::find_urls::WebScrapingError;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+const REPORT_PATH: &str = "./data/report.json";
+
+/// Site-wide summary written next to data/all_urls.json.
+/// Each section is filled in by the command that produces it.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct Report {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_analytics: Option<LinkSummary>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redirect_verification: Option<RedirectSummary>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seo: Option<SeoSummary>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canonical: Option<CanonicalSummary>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accessibility: Option<A11ySummary>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub console: Option<ConsoleSummary>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub performance: Option<PerformanceSummary>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lighthouse: Option<LighthouseSummary>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceInventory>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub security_headers: Option<HeaderMatrix>,
+}
+
+/// Loads data/report.json (or an empty report), lets `update` fill in its section and writes it back
+pub fn update_report<F>(update: F) -> Result<(), WebScrapingError>
+where
+    F: FnOnce(&mut Report),
+{
+    let mut report: Report = fs::read_to_string(Path::new(REPORT_PATH))
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default();
+
+    update(&mut report);
+
+    if fs::DirBuilder::new().recursive(true).create("./data").is_err() {
+        println!("Trouble creating data directory!");
+        return Err(WebScrapingError::WritingToFileError);
+    }
+    if let Ok(string) = serde_json::to_string_pretty(&report) {
+        if fs::write(Path::new(REPORT_PATH), string).is_ok() {
+            println!("Updated report {}", REPORT_PATH);
+            Ok(())
+        } else {
+            println!("Trouble writing report!");
+            Err(WebScrapingError::WritingToFileError)
+        }
+    } else {
+        println!("Trouble Parsing report!");
+        Err(WebScrapingError::WritingToFileError)
+    }
+}
@@ -0,0 +1,246 @@
+use crate::linkgraph::export_graph::escape_xml;
+use crate::webscraper::fetch::{http_client, last_modified};
+use crate::webscraper::find_urls::{read_from_file, Url, WebScrapingError};
+use crate::webscraper::resource::ResourceKind;
+use futures::stream::{self, StreamExt};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Limits from https://www.sitemaps.org/protocol.html
+const MAX_URLS_PER_SITEMAP: usize = 50_000;
+const MAX_BYTES_PER_SITEMAP: usize = 50 * 1024 * 1024;
+const LASTMOD_CONCURRENCY: usize = 8;
+
+const URLSET_HEADER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n";
+const URLSET_IMAGE_HEADER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\" xmlns:image=\"http://www.google.com/schemas/sitemap-image/1.1\">\n";
+const URLSET_FOOTER: &str = "</urlset>\n";
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SitemapOptions {
+    pub output_dir: String,
+    /// Where the sitemap files will be hosted. Needed for the urls in a sitemap index.
+    pub base_url: Option<String>,
+    /// Send a HEAD request for every page and use its Last-Modified header
+    pub lastmod: bool,
+    pub images: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SitemapEntry {
+    pub loc: String,
+    pub lastmod: Option<String>,
+    pub images: Vec<String>,
+}
+
+/// Pages that belong in a sitemap: html, 200, indexable and canonical
+pub fn sitemap_urls(index: &HashMap<String, Url>) -> Vec<&Url> {
+    let mut urls: Vec<&Url> = index
+        .values()
+        .filter(|url| url.response_code == Some(200) && url.is_indexable() && url.is_canonical())
+        // PDFs, images and archives answer 200 too but aren't pages
+        .filter(|url| url.resource_kind.is_none_or(|kind| kind == ResourceKind::Html))
+        .collect();
+    urls.sort_by(|a, b| a.full_path.cmp(&b.full_path));
+    urls
+}
+
+fn render_entry(entry: &SitemapEntry, images: bool) -> String {
+    let mut xml = format!("  <url>\n    <loc>{}</loc>\n", escape_xml(&entry.loc));
+    if let Some(lastmod) = &entry.lastmod {
+        xml.push_str(&format!("    <lastmod>{}</lastmod>\n", lastmod));
+    }
+    if images {
+        for image in entry.images.iter() {
+            xml.push_str(&format!(
+                "    <image:image>\n      <image:loc>{}</image:loc>\n    </image:image>\n",
+                escape_xml(image)
+            ));
+        }
+    }
+    xml.push_str("  </url>\n");
+    xml
+}
+
+/// Renders the entries into as many urlsets as needed to stay under the sitemap limits
+pub fn render_sitemaps(entries: &[SitemapEntry], images: bool) -> Vec<String> {
+    let header = if images { URLSET_IMAGE_HEADER } else { URLSET_HEADER };
+    let mut sitemaps = Vec::new();
+    let mut current = String::from(header);
+    let mut count = 0;
+
+    for entry in entries {
+        let xml = render_entry(entry, images);
+        if count == MAX_URLS_PER_SITEMAP
+            || (count > 0 && current.len() + xml.len() + URLSET_FOOTER.len() > MAX_BYTES_PER_SITEMAP)
+        {
+            current.push_str(URLSET_FOOTER);
+            sitemaps.push(current);
+            current = String::from(header);
+            count = 0;
+        }
+        current.push_str(&xml);
+        count += 1;
+    }
+    current.push_str(URLSET_FOOTER);
+    sitemaps.push(current);
+    sitemaps
+}
+
+pub fn render_sitemap_index(locations: &[String]) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    for location in locations {
+        xml.push_str(&format!("  <sitemap>\n    <loc>{}</loc>\n  </sitemap>\n", escape_xml(location)));
+    }
+    xml.push_str("</sitemapindex>\n");
+    xml
+}
+
+/// Writes sitemap.xml for the crawl at `input_path` into `options.output_dir`, split behind a sitemap index when large
+pub async fn generate_sitemap(input_path: &str, options: SitemapOptions) -> Result<(), WebScrapingError> {
+    let index = read_from_file(input_path)?;
+    let urls = sitemap_urls(&index);
+    println!("{} of {} urls are canonical, indexable and 200", urls.len(), index.len());
+
+    let mut entries: Vec<SitemapEntry> = urls
+        .iter()
+        .map(|url| SitemapEntry {
+            loc: url.full_path.clone(),
+            lastmod: None,
+            images: url.images.clone(),
+        })
+        .collect();
+
+    if options.lastmod {
+        println!("Requesting Last-Modified headers");
+        let client = http_client();
+        let dates: Vec<Option<String>> = stream::iter(entries.iter())
+            .map(|entry| last_modified(&client, &entry.loc))
+            .buffered(LASTMOD_CONCURRENCY)
+            .collect()
+            .await;
+        for (entry, date) in entries.iter_mut().zip(dates) {
+            entry.lastmod = date;
+        }
+    }
+
+    let sitemaps = render_sitemaps(&entries, options.images);
+    let output_dir = Path::new(&options.output_dir);
+    if fs::DirBuilder::new().recursive(true).create(output_dir).is_err() {
+        println!("Trouble creating output directory!");
+        return Err(WebScrapingError::WritingToFileError);
+    }
+
+    if sitemaps.len() == 1 {
+        write_file(&output_dir.join("sitemap.xml"), &sitemaps[0])?;
+        return Ok(());
+    }
+
+    let base_url = index_base_url(options.base_url.as_deref())?;
+    let mut locations = Vec::new();
+    for (number, sitemap) in sitemaps.iter().enumerate() {
+        let file_name = format!("sitemap-{}.xml", number + 1);
+        write_file(&output_dir.join(&file_name), sitemap)?;
+        locations.push(format!("{}/{}", base_url, file_name));
+    }
+    write_file(&output_dir.join("sitemap.xml"), &render_sitemap_index(&locations))
+}
+
+/// Where the split sitemaps are hosted, which the sitemap index has to link to by full url
+fn index_base_url(base_url: Option<&str>) -> Result<String, WebScrapingError> {
+    match base_url {
+        Some(base_url) => Ok(base_url.trim_end_matches('/').to_string()),
+        None => Err(WebScrapingError::ConfigError(
+            "the sitemap is too large for one file, --base-url is needed for its sitemap index".to_string(),
+        )),
+    }
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<(), WebScrapingError> {
+    println!("Writing {}", path.display());
+    if fs::write(path, contents).is_err() {
+        println!("Trouble writing data!");
+        return Err(WebScrapingError::WritingToFileError);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(full_path: &str, response_code: Option<u16>) -> (String, Url) {
+        (
+            full_path.to_string(),
+            Url {
+                full_path: full_path.to_string(),
+                response_code,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn entry(loc: &str) -> SitemapEntry {
+        SitemapEntry {
+            loc: loc.to_string(),
+            lastmod: None,
+            images: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn sitemap_urls_test() {
+        let mut index = HashMap::from([
+            url("https://example.com/", Some(200)),
+            url("https://example.com/missing", Some(404)),
+            url("https://example.com/moved", Some(300)),
+            url("https://example.com/private", Some(200)),
+            url("https://example.com/print", Some(200)),
+            url("https://example.com/about", Some(200)),
+            url("https://example.com/manual.pdf", Some(200)),
+        ]);
+        index.get_mut("https://example.com/").unwrap().resource_kind = Some(ResourceKind::Html);
+        index.get_mut("https://example.com/manual.pdf").unwrap().resource_kind = Some(ResourceKind::Pdf);
+        index.get_mut("https://example.com/private").unwrap().robots = Some("NOINDEX, follow".to_string());
+        index.get_mut("https://example.com/print").unwrap().canonical = Some("https://example.com/about".to_string());
+        index.get_mut("https://example.com/about").unwrap().canonical = Some("https://example.com/about/".to_string());
+
+        let locs: Vec<&str> = sitemap_urls(&index).iter().map(|url| url.full_path.as_str()).collect();
+        assert_eq!(locs, vec!["https://example.com/", "https://example.com/about"]);
+    }
+
+    #[test]
+    fn render_sitemaps_test() {
+        let mut with_extras = entry("https://example.com/?a=1&b=2");
+        with_extras.lastmod = Some("2022-01-03".to_string());
+        with_extras.images = vec!["https://example.com/hero.png".to_string()];
+
+        assert_eq!(
+            render_sitemaps(&[with_extras], true),
+            vec![format!(
+                "{}  <url>\n    <loc>https://example.com/?a=1&amp;b=2</loc>\n    <lastmod>2022-01-03</lastmod>\n    \
+                 <image:image>\n      <image:loc>https://example.com/hero.png</image:loc>\n    </image:image>\n  </url>\n{}",
+                URLSET_IMAGE_HEADER, URLSET_FOOTER
+            )]
+        );
+    }
+
+    #[test]
+    fn render_sitemaps_splits_test() {
+        let entries: Vec<SitemapEntry> = (0..MAX_URLS_PER_SITEMAP + 1)
+            .map(|i| entry(&format!("https://example.com/{}", i)))
+            .collect();
+
+        let sitemaps = render_sitemaps(&entries, false);
+        assert_eq!(sitemaps.len(), 2);
+        assert_eq!(sitemaps[1].matches("<url>").count(), 1);
+    }
+
+    #[test]
+    fn index_base_url_test() {
+        assert_eq!(index_base_url(Some("https://example.com/")).unwrap(), "https://example.com");
+        assert!(matches!(index_base_url(None), Err(WebScrapingError::ConfigError(_))));
+    }
+}
@@ -0,0 +1,204 @@
+use fantoccini::wd::Capabilities;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct WindowSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl WindowSize {
+    /// Parses `1920x1080`
+    pub fn parse(value: &str) -> Option<WindowSize> {
+        let value = value.to_lowercase();
+        let (width, height) = value.split_once('x')?;
+        Some(WindowSize {
+            width: width.trim().parse().ok()?,
+            height: height.trim().parse().ok()?,
+        })
+    }
+}
+
+/// Browser settings from the `[browser]` section of the config file and the command line
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BrowserOptions {
+    pub headless: bool,
+    pub window_size: Option<WindowSize>,
+    pub user_agent: Option<String>,
+    /// Browser language and Accept-Language, e.g. `de-DE`
+    pub locale: Option<String>,
+    /// Extra command line arguments for the browser
+    pub args: Vec<String>,
+    /// Only used by Firefox
+    pub firefox_prefs: BTreeMap<String, Value>,
+    /// Don't load images or web fonts, for crawls that only need links
+    pub disable_images: bool,
+    pub disable_fonts: bool,
+    /// The browser to request from a remote server: firefox, chrome or MicrosoftEdge
+    pub browser_name: Option<String>,
+}
+
+/// https://developer.mozilla.org/en-US/docs/Web/WebDriver/Capabilities/firefoxOptions
+pub fn firefox_capabilities(options: &BrowserOptions) -> Capabilities {
+    let mut args: Vec<String> = Vec::new();
+    if options.headless {
+        args.push("-headless".to_string());
+    }
+    if let Some(size) = options.window_size {
+        args.push(format!("--width={}", size.width));
+        args.push(format!("--height={}", size.height));
+    }
+    args.extend(options.args.iter().cloned());
+
+    let mut prefs: Map<String, Value> = Map::new();
+    if let Some(user_agent) = &options.user_agent {
+        prefs.insert("general.useragent.override".to_string(), json!(user_agent));
+    }
+    if let Some(locale) = &options.locale {
+        prefs.insert("intl.accept_languages".to_string(), json!(locale));
+        prefs.insert("intl.locale.requested".to_string(), json!(locale));
+    }
+    if options.disable_images {
+        prefs.insert("permissions.default.image".to_string(), json!(2));
+    }
+    if options.disable_fonts {
+        prefs.insert("browser.display.use_document_fonts".to_string(), json!(0));
+        prefs.insert("gfx.downloadable_fonts.enabled".to_string(), json!(false));
+    }
+    for (name, value) in options.firefox_prefs.iter() {
+        prefs.insert(name.clone(), value.clone());
+    }
+
+    let mut capabilities = Capabilities::new();
+    capabilities.insert("browserName".to_string(), json!("firefox"));
+    capabilities.insert("moz:firefoxOptions".to_string(), json!({ "args": args, "prefs": prefs }));
+    capabilities
+}
+
+/// Chrome and Edge share the same switches and preferences
+fn chromium_options(options: &BrowserOptions) -> Value {
+    let mut args: Vec<String> = Vec::new();
+    if options.headless {
+        args.push("--headless".to_string());
+    }
+    if let Some(size) = options.window_size {
+        args.push(format!("--window-size={},{}", size.width, size.height));
+    }
+    if let Some(user_agent) = &options.user_agent {
+        args.push(format!("--user-agent={}", user_agent));
+    }
+    if let Some(locale) = &options.locale {
+        args.push(format!("--lang={}", locale));
+    }
+    if options.disable_fonts {
+        args.push("--disable-remote-fonts".to_string());
+    }
+    args.extend(options.args.iter().cloned());
+
+    let mut prefs: Map<String, Value> = Map::new();
+    if let Some(locale) = &options.locale {
+        prefs.insert("intl.accept_languages".to_string(), json!(locale));
+    }
+    if options.disable_images {
+        prefs.insert("profile.managed_default_content_settings.images".to_string(), json!(2));
+    }
+
+    json!({ "args": args, "prefs": prefs })
+}
+
+/// https://chromedriver.chromium.org/capabilities
+pub fn chrome_capabilities(options: &BrowserOptions) -> Capabilities {
+    let mut capabilities = Capabilities::new();
+    capabilities.insert("browserName".to_string(), json!("chrome"));
+    capabilities.insert("goog:chromeOptions".to_string(), chromium_options(options));
+    capabilities
+}
+
+/// https://learn.microsoft.com/en-us/microsoft-edge/webdriver-chromium/capabilities-edge-options
+pub fn edge_capabilities(options: &BrowserOptions) -> Capabilities {
+    let mut capabilities = Capabilities::new();
+    capabilities.insert("browserName".to_string(), json!("MicrosoftEdge"));
+    capabilities.insert("ms:edgeOptions".to_string(), chromium_options(options));
+    capabilities
+}
+
+/// A remote server picks the browser unless `browser_name` is configured
+pub fn remote_capabilities(options: &BrowserOptions) -> Capabilities {
+    match options.browser_name.as_deref().map(str::to_lowercase).as_deref() {
+        Some("firefox") => firefox_capabilities(options),
+        Some("chrome") | Some("chromium") => chrome_capabilities(options),
+        Some("microsoftedge") | Some("edge") => edge_capabilities(options),
+        _ => Capabilities::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_size_parse_test() {
+        assert_eq!(
+            WindowSize::parse("1920x1080"),
+            Some(WindowSize {
+                width: 1920,
+                height: 1080
+            })
+        );
+        assert_eq!(WindowSize::parse("1920"), None);
+    }
+
+    #[test]
+    fn firefox_capabilities_test() {
+        let options = BrowserOptions {
+            headless: true,
+            locale: Some("de-DE".to_string()),
+            disable_images: true,
+            firefox_prefs: BTreeMap::from([("permissions.default.image".to_string(), json!(1))]),
+            ..Default::default()
+        };
+
+        let capabilities = firefox_capabilities(&options);
+        let firefox_options = &capabilities["moz:firefoxOptions"];
+        assert_eq!(firefox_options["args"], json!(["-headless"]));
+        assert_eq!(firefox_options["prefs"]["intl.accept_languages"], "de-DE");
+        // Prefs from the config file win over the generated ones
+        assert_eq!(firefox_options["prefs"]["permissions.default.image"], 1);
+    }
+
+    #[test]
+    fn chrome_capabilities_test() {
+        let options = BrowserOptions {
+            headless: true,
+            window_size: WindowSize::parse("800x600"),
+            user_agent: Some("audit-bot".to_string()),
+            args: vec!["--no-sandbox".to_string()],
+            disable_images: true,
+            ..Default::default()
+        };
+
+        let capabilities = chrome_capabilities(&options);
+        assert_eq!(
+            capabilities["goog:chromeOptions"]["args"],
+            json!(["--headless", "--window-size=800,600", "--user-agent=audit-bot", "--no-sandbox"])
+        );
+        assert_eq!(
+            capabilities["goog:chromeOptions"]["prefs"]["profile.managed_default_content_settings.images"],
+            2
+        );
+    }
+
+    #[test]
+    fn remote_capabilities_test() {
+        assert!(remote_capabilities(&BrowserOptions::default()).is_empty());
+
+        let options = BrowserOptions {
+            browser_name: Some("chrome".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(remote_capabilities(&options)["browserName"], "chrome");
+    }
+}
@@ -1 +1,4 @@
-pub mod webdriver;
+pub mod capabilities;
+pub mod session;
+#[allow(clippy::module_inception)]
+pub mod webdriver;
@@ -0,0 +1,153 @@
+use crate::webdriver::capabilities::BrowserOptions;
+use crate::webdriver::webdriver::{DriverHandle, WebDriver};
+use crate::audit::weight::RESOURCE_TIMING_PRELOAD_SCRIPT;
+use crate::webscraper::console::console_preload_script;
+use crate::webscraper::find_urls::WebScrapingError;
+use crate::webscraper::page_load::Timeouts;
+use fantoccini::error::CmdError;
+use fantoccini::{Client, ClientBuilder};
+
+/// A driver process together with the browser session open on it.
+/// The session can be torn down and started again when the browser crashes,
+/// and is recycled every `recycle_after` pages to keep browser memory bounded.
+pub struct BrowserSession {
+    webdriver: DriverHandle,
+    client: Client,
+    driver_type: WebDriver,
+    browser_options: BrowserOptions,
+    timeouts: Timeouts,
+    recycle_after: Option<usize>,
+    pages_visited: usize,
+}
+
+impl BrowserSession {
+    pub async fn start(
+        driver_type: WebDriver,
+        browser_options: BrowserOptions,
+        timeouts: Timeouts,
+        recycle_after: Option<usize>,
+    ) -> Result<Self, WebScrapingError> {
+        //Launches WebDriver
+        let webdriver = DriverHandle::new(driver_type.clone()).await?;
+
+        println!("Opening Up Web Client");
+        let client = open_new_client(&webdriver, &browser_options, &timeouts).await?;
+        println!("Connected to Web Client");
+
+        Ok(BrowserSession {
+            webdriver,
+            client,
+            driver_type,
+            browser_options,
+            timeouts,
+            recycle_after,
+            pages_visited: 0,
+        })
+    }
+
+    pub fn client(&mut self) -> &mut Client {
+        &mut self.client
+    }
+
+    /// Closes the browser and driver (ignoring errors, they may already be dead) and starts fresh ones
+    pub async fn restart(&mut self) -> Result<(), WebScrapingError> {
+        println!("Restarting browser session");
+        let _ = self.client.close().await;
+        if let Err(e) = self.webdriver.kill() {
+            println!("Error closing Webdriver: {}", e);
+        }
+
+        self.webdriver = DriverHandle::new(self.driver_type.clone()).await?;
+        self.client = open_new_client(&self.webdriver, &self.browser_options, &self.timeouts).await?;
+        self.pages_visited = 0;
+        Ok(())
+    }
+
+    /// Counts a visited page and recycles the session once `recycle_after` pages were loaded
+    pub async fn page_visited(&mut self) -> Result<(), WebScrapingError> {
+        self.pages_visited += 1;
+        if let Some(recycle_after) = self.recycle_after {
+            if self.pages_visited >= recycle_after {
+                println!("Recycling browser session after {} pages", self.pages_visited);
+                self.restart().await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn close(mut self) -> Result<(), WebScrapingError> {
+        println!("Closing to Web Client");
+        self.client.close().await?;
+        println!("Closed to Web Client");
+
+        //Exits the driver
+        if let Err(e) = self.webdriver.kill() {
+            println!("Error closing Webdriver: {}", e);
+        }
+        Ok(())
+    }
+}
+
+async fn open_new_client(
+    webdriver: &DriverHandle,
+    browser_options: &BrowserOptions,
+    timeouts: &Timeouts,
+) -> Result<Client, WebScrapingError> {
+    let mut capabilities = webdriver.capabilities(browser_options);
+    capabilities.insert("timeouts".to_string(), timeouts.to_capability());
+    let mut web_client = ClientBuilder::native()
+        .capabilities(capabilities)
+        .connect(&webdriver.url())
+        .await?;
+    if let Some(size) = browser_options.window_size {
+        web_client.set_window_size(size.width, size.height).await?;
+    }
+    // Listening from the start of every document catches errors thrown while the page loads, and
+    // the resource timing buffer can only grow before it filled up
+    if let Some(session_id) = web_client.session_id().await? {
+        let preload = format!("{}{}", console_preload_script(), RESOURCE_TIMING_PRELOAD_SCRIPT);
+        let user_agent = web_client.execute("return navigator.userAgent;", Vec::new()).await?;
+        let user_agent = user_agent.as_str().unwrap_or_default();
+        if let Err(e) = webdriver
+            .add_preload_script(&session_id, browser_options, user_agent, &preload)
+            .await
+        {
+            println!(
+                "Console messages are only recorded once a page loaded, and resources past the 250th are missed: {}",
+                e
+            );
+        }
+    }
+    Ok(web_client)
+}
+
+/// Errors that mean the browser or driver is gone, so every following command would fail too
+pub fn is_session_dead(error: &CmdError) -> bool {
+    match error {
+        CmdError::Lost(_) | CmdError::Failed(_) | CmdError::NoSuchWindow(_) => true,
+        CmdError::Standard(e) => {
+            let message = e.message.to_lowercase();
+            matches!(e.error(), "invalid session id" | "session not created")
+                || message.contains("browsing context has been discarded")
+                || message.contains("without establishing a connection")
+                || message.contains("not reachable")
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn is_session_dead_test() {
+        assert!(is_session_dead(&CmdError::Lost(io::Error::new(
+            io::ErrorKind::ConnectionReset,
+            "connection reset"
+        ))));
+        assert!(!is_session_dead(&CmdError::NotJson("<html>".to_string())));
+        assert!(!is_session_dead(&CmdError::WaitTimeout));
+    }
+}
@@ -1,53 +1,275 @@
-use std::process::Child;
-use std::process::Command;
+use crate::webdriver::capabilities::{
+    chrome_capabilities, edge_capabilities, firefox_capabilities, remote_capabilities, BrowserOptions,
+};
+use fantoccini::wd::Capabilities;
+use serde_json::{json, Value};
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
+const LOG_DIRECTORY: &str = "./data/logs";
+/// Content scripts that run in the page rather than an isolated world need Firefox 128
+const MIN_FIREFOX_PRELOAD_VERSION: u32 = 128;
+const READY_TIMEOUT: Duration = Duration::from_secs(30);
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A running webdriver process, or a remote server we only connect to.
+/// A spawned process is killed when the handle is dropped.
 pub struct DriverHandle {
-    process: Child,
+    process: Option<Child>,
+    url: String,
+    log_path: Option<PathBuf>,
+    driver_type: WebDriver,
+    /// Where the Firefox add-on preloading scripts is written, removed with the handle
+    preload_addon_directory: PathBuf,
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
 pub enum WebDriver {
+    #[default]
     GeckoDriver,
+    ChromeDriver,
+    MsEdgeDriver,
+    /// An already running Selenium server or driver, e.g. http://localhost:4444/wd/hub
+    Remote { url: String },
 }
 
 #[derive(Debug)]
 pub enum WebDriverError {
     UnableToCloseProgram(String),
+    UnableToStartProgram(String),
+    NoFreePort,
+    /// The driver didn't report ready on /status before the timeout
+    NotReady(String),
+    /// The driver can't run a script at the start of every document
+    PreloadUnsupported(String),
+}
+
+impl fmt::Display for WebDriverError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnableToCloseProgram(reason) => write!(f, "unable to close webdriver: {}", reason),
+            Self::UnableToStartProgram(reason) => write!(f, "unable to start webdriver: {}", reason),
+            Self::NoFreePort => write!(f, "could not find a free port for the webdriver"),
+            Self::NotReady(reason) => write!(f, "webdriver never became ready: {}", reason),
+            Self::PreloadUnsupported(reason) => write!(f, "could not preload script: {}", reason),
+        }
+    }
+}
+
+impl WebDriver {
+    /// Parses the --driver option. `remote` needs the server url.
+    pub fn from_name(name: &str, remote_url: Option<&str>) -> Option<WebDriver> {
+        match name.to_lowercase().as_str() {
+            "gecko" | "geckodriver" | "firefox" => Some(WebDriver::GeckoDriver),
+            "chrome" | "chromedriver" | "chromium" => Some(WebDriver::ChromeDriver),
+            "edge" | "msedgedriver" => Some(WebDriver::MsEdgeDriver),
+            "remote" => remote_url.map(|url| WebDriver::Remote {
+                url: url.trim_end_matches('/').to_string(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// The executable to spawn, None for a remote server
+    fn program(&self) -> Option<&'static str> {
+        match self {
+            WebDriver::GeckoDriver => Some("geckodriver"),
+            WebDriver::ChromeDriver => Some("chromedriver"),
+            WebDriver::MsEdgeDriver => Some("msedgedriver"),
+            WebDriver::Remote { .. } => None,
+        }
+    }
+
+    fn port_args(&self, port: u16) -> Vec<String> {
+        match self {
+            WebDriver::GeckoDriver => vec!["--port".to_string(), port.to_string()],
+            _ => vec![format!("--port={}", port)],
+        }
+    }
+
+    pub fn capabilities(&self, options: &BrowserOptions) -> Capabilities {
+        match self {
+            WebDriver::GeckoDriver => firefox_capabilities(options),
+            WebDriver::ChromeDriver => chrome_capabilities(options),
+            WebDriver::MsEdgeDriver => edge_capabilities(options),
+            WebDriver::Remote { .. } => remote_capabilities(options),
+        }
+    }
 }
 
 impl DriverHandle {
-    pub fn new(driver_type: WebDriver) -> Self {
+    /// Spawns the driver on a free port, appending to data/logs/<driver>.log,
+    /// and waits until its /status endpoint reports ready.
+    /// Remote servers are not spawned, only checked for readiness.
+    pub async fn new(driver_type: WebDriver) -> Result<Self, WebDriverError> {
         println!("Creating WebDriver");
-        if cfg!(target_os = "windows") {
-            match driver_type {
-                WebDriver::GeckoDriver => {
-                    return DriverHandle {
-                        process: Command::new("geckodriver")
-                            .spawn()
-                            .expect("command failed to start"),
-                    }
-                },
-                //TODO: add more compatible Drivers
+        if let WebDriver::Remote { url } = &driver_type {
+            let mut handle = DriverHandle {
+                process: None,
+                url: url.clone(),
+                log_path: None,
+                driver_type,
+                preload_addon_directory: preload_addon_directory(),
+            };
+            handle.wait_until_ready(READY_TIMEOUT).await?;
+            println!("Connected to remote webdriver {}", handle.url);
+            return Ok(handle);
+        }
+        let program = driver_type.program().unwrap_or("geckodriver");
+        let port = free_port()?;
+
+        let log_path = Path::new(LOG_DIRECTORY).join(format!("{}.log", program));
+        // Appended to, so a restart after a crash keeps the output of the driver that crashed
+        let log_file = fs::DirBuilder::new()
+            .recursive(true)
+            .create(LOG_DIRECTORY)
+            .and_then(|_| fs::OpenOptions::new().create(true).append(true).open(&log_path))
+            .and_then(|mut log_file| {
+                writeln!(log_file, "--- starting {} on port {} ---", program, port)?;
+                Ok(log_file)
+            })
+            .map_err(|e| WebDriverError::UnableToStartProgram(format!("could not create log file: {}", e)))?;
+        let stderr = log_file
+            .try_clone()
+            .map_err(|e| WebDriverError::UnableToStartProgram(format!("could not create log file: {}", e)))?;
+
+        let process = Command::new(program)
+            .args(driver_type.port_args(port))
+            .stdout(Stdio::from(log_file))
+            .stderr(Stdio::from(stderr))
+            .spawn()
+            .map_err(|e| WebDriverError::UnableToStartProgram(format!("{}: {}", program, e)))?;
+
+        let mut handle = DriverHandle {
+            process: Some(process),
+            url: format!("http://localhost:{}", port),
+            log_path: Some(log_path),
+            driver_type,
+            preload_addon_directory: preload_addon_directory(),
+        };
+        handle.wait_until_ready(READY_TIMEOUT).await?;
+        println!("{} listening on port {}", program, port);
+        Ok(handle)
+    }
+
+    /// The address fantoccini should connect to
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    /// Session capabilities for the browser this driver controls
+    pub fn capabilities(&self, options: &BrowserOptions) -> Capabilities {
+        self.driver_type.capabilities(options)
+    }
+
+    fn log_hint(&self) -> String {
+        match &self.log_path {
+            Some(log_path) => format!(", see {}", log_path.display()),
+            None => String::new(),
+        }
+    }
+
+    async fn wait_until_ready(&mut self, timeout: Duration) -> Result<(), WebDriverError> {
+        let client = reqwest::Client::new();
+        let status_url = format!("{}/status", self.url());
+        let started = Instant::now();
+
+        loop {
+            if let Some(process) = self.process.as_mut() {
+                if let Ok(Some(status)) = process.try_wait() {
+                    return Err(WebDriverError::UnableToStartProgram(format!(
+                        "driver exited with {}{}",
+                        status,
+                        self.log_hint()
+                    )));
+                }
             }
-        } else if cfg!(target_os = "linux") {
-            println!("Running configuration for linux");
-            match driver_type {
-                WebDriver::GeckoDriver => {
-                    return DriverHandle {
-                        process: Command::new("geckodriver")
-                            .spawn()
-                            .expect("command failed to start"),
+            if let Ok(response) = client.get(&status_url).send().await {
+                if let Ok(body) = response.json::<Value>().await {
+                    if is_ready(&body) {
+                        return Ok(());
                     }
-                },
-                //Add more compatible Drivers later
+                }
+            }
+            if started.elapsed() > timeout {
+                return Err(WebDriverError::NotReady(format!(
+                    "no ready response from {} after {:?}{}",
+                    status_url,
+                    timeout,
+                    self.log_hint()
+                )));
+            }
+            tokio::time::sleep(READY_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Runs `source` at the start of every document the session opens, before the page's own
+    /// scripts. Chrome and Edge get it over their CDP endpoint, Firefox as a temporary add-on with
+    /// a main world content script, which older versions than 128 refuse. A remote Firefox can't
+    /// read the add-on from this machine and isn't supported.
+    pub async fn add_preload_script(
+        &self,
+        session_id: &str,
+        options: &BrowserOptions,
+        user_agent: &str,
+        source: &str,
+    ) -> Result<(), WebDriverError> {
+        let remote_browser = options.browser_name.as_deref().map(str::to_lowercase);
+        let (endpoint, body) = match (&self.driver_type, remote_browser.as_deref()) {
+            (WebDriver::GeckoDriver, _) => match firefox_version(user_agent) {
+                Some(version) if version < MIN_FIREFOX_PRELOAD_VERSION => {
+                    return Err(WebDriverError::PreloadUnsupported(format!(
+                        "Firefox {} can't run add-on scripts in the page, {} or newer is needed",
+                        version, MIN_FIREFOX_PRELOAD_VERSION
+                    )))
+                }
+                _ => (
+                    "moz/addon/install",
+                    preload_addon(&self.preload_addon_directory, source)?,
+                ),
+            },
+            (WebDriver::Remote { .. }, Some("firefox")) => {
+                return Err(WebDriverError::PreloadUnsupported(
+                    "remote Firefox sessions can't install a local add-on".to_string(),
+                ))
+            }
+            (WebDriver::MsEdgeDriver, _) | (WebDriver::Remote { .. }, Some("microsoftedge" | "edge")) => {
+                ("ms/cdp/execute", cdp_preload(source))
             }
+            (WebDriver::ChromeDriver, _) | (WebDriver::Remote { .. }, _) => ("goog/cdp/execute", cdp_preload(source)),
+        };
+
+        let url = format!("{}/session/{}/{}", self.url(), session_id, endpoint);
+        let response = reqwest::Client::new()
+            .post(&url)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| WebDriverError::PreloadUnsupported(e.to_string()))?;
+        if response.status().is_success() {
+            Ok(())
         } else {
-            panic!("Didn't recognize os system!");
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            Err(WebDriverError::PreloadUnsupported(format!("{} answered {}: {}", url, status, body)))
         }
     }
 
     pub fn kill(&mut self) -> Result<(), WebDriverError> {
         println!("Closing Webdriver");
-        if let Ok(_) = self.process.kill() {
+        let process = match self.process.as_mut() {
+            Some(process) => process,
+            // Remote servers are left running
+            None => return Ok(()),
+        };
+        if process.kill().is_ok() {
+            let _ = process.wait();
             Ok(())
         } else {
             Err(WebDriverError::UnableToCloseProgram(String::from(
@@ -56,3 +278,150 @@ impl DriverHandle {
         }
     }
 }
+
+impl Drop for DriverHandle {
+    fn drop(&mut self) {
+        if let Some(process) = self.process.as_mut() {
+            if let Ok(None) = process.try_wait() {
+                let _ = process.kill();
+                let _ = process.wait();
+            }
+        }
+        let _ = fs::remove_dir_all(&self.preload_addon_directory);
+    }
+}
+
+/// A new directory per driver, so two crawls side by side, or a restarted session, never rewrite
+/// an add-on Firefox is still loading
+fn preload_addon_directory() -> PathBuf {
+    static NEXT_ADDON: AtomicUsize = AtomicUsize::new(0);
+    let number = NEXT_ADDON.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("web_audit_preload_{}_{}", process::id(), number))
+}
+
+/// Major version from a Firefox user agent, e.g. `... Gecko/20100101 Firefox/128.0`
+fn firefox_version(user_agent: &str) -> Option<u32> {
+    let version = user_agent.split("Firefox/").nth(1)?;
+    version.split('.').next()?.parse().ok()
+}
+
+fn cdp_preload(source: &str) -> Value {
+    json!({
+        "cmd": "Page.addScriptToEvaluateOnNewDocument",
+        "params": { "source": source },
+    })
+}
+
+/// Writes an unpacked add-on running `source` in the page itself at document_start into
+/// `directory`, and returns the request installing it
+fn preload_addon(directory: &Path, source: &str) -> Result<Value, WebDriverError> {
+    let manifest = json!({
+        "manifest_version": 3,
+        "name": "web_audit preload",
+        "version": "1.0",
+        "browser_specific_settings": { "gecko": { "id": "preload@web-audit" } },
+        "content_scripts": [{
+            "matches": ["<all_urls>"],
+            "js": ["preload.js"],
+            "run_at": "document_start",
+            "world": "MAIN",
+        }],
+    });
+    let path = fs::DirBuilder::new()
+        .recursive(true)
+        .create(directory)
+        .and_then(|_| fs::write(directory.join("manifest.json"), manifest.to_string()))
+        .and_then(|_| fs::write(directory.join("preload.js"), source))
+        .and_then(|_| fs::canonicalize(directory))
+        .map_err(|e| WebDriverError::PreloadUnsupported(format!("could not write the add-on: {}", e)))?;
+    Ok(json!({ "path": path, "temporary": true }))
+}
+
+/// Asks the OS for an unused port. The listener is closed before the driver binds it.
+fn free_port() -> Result<u16, WebDriverError> {
+    TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .map(|address| address.port())
+        .map_err(|_| WebDriverError::NoFreePort)
+}
+
+/// W3C drivers answer /status with `{"value": {"ready": true, ...}}`
+fn is_ready(status: &Value) -> bool {
+    status["value"]["ready"].as_bool().unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn free_port_test() {
+        let port = free_port().unwrap();
+        assert!(port > 0);
+        assert!(TcpListener::bind(("127.0.0.1", port)).is_ok());
+    }
+
+    #[test]
+    fn from_name_test() {
+        assert_eq!(WebDriver::from_name("gecko", None), Some(WebDriver::GeckoDriver));
+        assert_eq!(WebDriver::from_name("Chrome", None), Some(WebDriver::ChromeDriver));
+        assert_eq!(WebDriver::from_name("edge", None), Some(WebDriver::MsEdgeDriver));
+        assert_eq!(
+            WebDriver::from_name("remote", Some("http://grid:4444/wd/hub/")),
+            Some(WebDriver::Remote {
+                url: "http://grid:4444/wd/hub".to_string()
+            })
+        );
+        assert_eq!(WebDriver::from_name("remote", None), None);
+        assert_eq!(WebDriver::from_name("safari", None), None);
+    }
+
+    #[test]
+    fn capabilities_test() {
+        let options = BrowserOptions::default();
+        assert_eq!(WebDriver::ChromeDriver.capabilities(&options)["browserName"], "chrome");
+        assert!(WebDriver::MsEdgeDriver.capabilities(&options).contains_key("ms:edgeOptions"));
+        assert!(WebDriver::GeckoDriver.capabilities(&options).contains_key("moz:firefoxOptions"));
+        assert_eq!(WebDriver::GeckoDriver.port_args(4444), vec!["--port", "4444"]);
+        assert_eq!(WebDriver::ChromeDriver.port_args(9515), vec!["--port=9515"]);
+    }
+
+    #[test]
+    fn firefox_version_test() {
+        assert_eq!(
+            firefox_version("Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0"),
+            Some(128)
+        );
+        assert_eq!(firefox_version("Mozilla/5.0 (X11; Linux x86_64) Chrome/126.0.0.0 Safari/537.36"), None);
+    }
+
+    #[test]
+    fn preload_addon_test() {
+        let directory = preload_addon_directory();
+        assert_ne!(directory, preload_addon_directory());
+
+        let install = preload_addon(&directory, "console.log(1);").unwrap();
+        assert_eq!(install["temporary"], true);
+        assert_eq!(fs::read_to_string(directory.join("preload.js")).unwrap(), "console.log(1);");
+        let manifest = fs::read_to_string(directory.join("manifest.json")).unwrap();
+        let manifest: Value = serde_json::from_str(&manifest).unwrap();
+        assert_eq!(manifest["content_scripts"][0]["world"], "MAIN");
+
+        drop(DriverHandle {
+            process: None,
+            url: "http://localhost:4444".to_string(),
+            log_path: None,
+            driver_type: WebDriver::GeckoDriver,
+            preload_addon_directory: directory.clone(),
+        });
+        assert!(!directory.exists());
+    }
+
+    #[test]
+    fn is_ready_test() {
+        assert!(is_ready(&json!({"value": {"ready": true, "message": ""}})));
+        assert!(!is_ready(&json!({"value": {"ready": false, "message": "Session already started"}})));
+        assert!(!is_ready(&json!({})));
+    }
+}
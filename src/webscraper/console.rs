@@ -0,0 +1,186 @@
+use crate::webscraper::find_urls::Url;
+use fantoccini::error::CmdError;
+use fantoccini::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Records console.error/console.warn calls, uncaught exceptions and unhandled promise rejections
+/// into `window.__webAuditConsole`. Installed once per document, so running it twice is harmless.
+const INSTALL_CONSOLE_SCRIPT: &str = r#"
+    if (window.__webAuditConsole) return;
+    const messages = window.__webAuditConsole = [];
+    const location = stack => {
+        const lines = String(stack || '').split('\n');
+        for (const line of lines) {
+            const match = line.match(/((?:https?|file):\/\/[^\s()]+?):(\d+):\d+/);
+            if (match) return { source: match[1], line: Number(match[2]) };
+        }
+        return {};
+    };
+    const text = value => {
+        if (value instanceof Error) return value.name + ': ' + value.message;
+        if (typeof value === 'string') return value;
+        try { return JSON.stringify(value); } catch (e) { return String(value); }
+    };
+    for (const [method, level] of [['error', 'error'], ['warn', 'warning']]) {
+        const original = console[method];
+        console[method] = function (...args) {
+            // Drop this wrapper's own frame so the location points at the caller
+            const stack = (new Error().stack || '').split('\n').slice(2).join('\n');
+            messages.push({ level, message: args.map(text).join(' '), ...location(stack) });
+            return original.apply(this, args);
+        };
+    }
+    window.addEventListener('error', event => {
+        messages.push({
+            level: 'exception',
+            message: event.error ? text(event.error) : event.message,
+            source: event.filename || undefined,
+            line: event.lineno || undefined,
+        });
+    });
+    window.addEventListener('unhandledrejection', event => {
+        messages.push({
+            level: 'exception',
+            message: 'Unhandled rejection: ' + text(event.reason),
+            ...location(event.reason && event.reason.stack),
+        });
+    });
+"#;
+
+/// `INSTALL_CONSOLE_SCRIPT` as a document script rather than a function body
+pub fn console_preload_script() -> String {
+    format!("(function () {{{}}})();", INSTALL_CONSOLE_SCRIPT)
+}
+
+const COLLECT_CONSOLE_SCRIPT: &str = "return window.__webAuditConsole || [];";
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConsoleLevel {
+    Warning,
+    Error,
+    /// An uncaught exception or unhandled promise rejection
+    Exception,
+}
+
+/// Something a page wrote to the console or threw
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
+pub struct ConsoleMessage {
+    pub level: ConsoleLevel,
+    pub message: String,
+    /// Script the message came from, when the browser tells
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+}
+
+/// Starts recording the console of the page that was just opened, unless `console_preload_script`
+/// already did when the document started. Only what happens after this call is seen then.
+pub async fn install_console_listeners(client: &mut Client) -> Result<(), CmdError> {
+    client.execute(INSTALL_CONSOLE_SCRIPT, Vec::new()).await?;
+    Ok(())
+}
+
+/// Everything recorded since `install_console_listeners`
+pub async fn collect_console_messages(client: &mut Client) -> Result<Vec<ConsoleMessage>, CmdError> {
+    let value = client.execute(COLLECT_CONSOLE_SCRIPT, Vec::new()).await?;
+    Ok(serde_json::from_value(value).unwrap_or_default())
+}
+
+/// One console message and every page it appeared on
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ConsoleGroup {
+    pub level: ConsoleLevel,
+    pub message: String,
+    pub source: Option<String>,
+    pub line: Option<u32>,
+    pub urls: Vec<String>,
+}
+
+/// The `console` section of the report, exceptions first, then by the number of pages affected
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct ConsoleSummary {
+    pub pages_with_errors: usize,
+    pub pages_with_warnings: usize,
+    pub messages: Vec<ConsoleGroup>,
+}
+
+pub fn console_summary(index: &HashMap<String, Url>) -> ConsoleSummary {
+    let mut groups: BTreeMap<&ConsoleMessage, BTreeSet<String>> = BTreeMap::new();
+    let mut summary = ConsoleSummary::default();
+
+    for url in index.values() {
+        if url.console.iter().any(|message| message.level != ConsoleLevel::Warning) {
+            summary.pages_with_errors += 1;
+        }
+        if url.console.iter().any(|message| message.level == ConsoleLevel::Warning) {
+            summary.pages_with_warnings += 1;
+        }
+        for message in url.console.iter() {
+            groups.entry(message).or_default().insert(url.full_path.clone());
+        }
+    }
+
+    summary.messages = groups
+        .into_iter()
+        .map(|(message, urls)| ConsoleGroup {
+            level: message.level,
+            message: message.message.clone(),
+            source: message.source.clone(),
+            line: message.line,
+            urls: urls.into_iter().collect(),
+        })
+        .collect();
+    summary
+        .messages
+        .sort_by(|a, b| b.level.cmp(&a.level).then(b.urls.len().cmp(&a.urls.len())));
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn page(path: &str, messages: Vec<ConsoleMessage>) -> (String, Url) {
+        let url = Url {
+            console: messages,
+            ..Url::new(path.to_string(), Some(200), "https://example.com/".to_string())
+        };
+        (path.to_string(), url)
+    }
+
+    #[test]
+    fn console_summary_test() {
+        let messages: Vec<ConsoleMessage> = serde_json::from_value(json!([
+            { "level": "exception", "message": "TypeError: cart is undefined", "source": "https://example.com/app.js", "line": 12 },
+            { "level": "warning", "message": "Deprecated API" },
+        ]))
+        .unwrap();
+        let index = HashMap::from([
+            page("https://example.com/a", messages.clone()),
+            page("https://example.com/b", vec![messages[0].clone()]),
+            page("https://example.com/c", vec![messages[1].clone()]),
+            page("https://example.com/d", Vec::new()),
+        ]);
+
+        let summary = console_summary(&index);
+
+        assert_eq!(summary.pages_with_errors, 2);
+        assert_eq!(summary.pages_with_warnings, 2);
+        assert_eq!(
+            summary.messages[0],
+            ConsoleGroup {
+                level: ConsoleLevel::Exception,
+                message: "TypeError: cart is undefined".to_string(),
+                source: Some("https://example.com/app.js".to_string()),
+                line: Some(12),
+                urls: vec!["https://example.com/a".to_string(), "https://example.com/b".to_string()],
+            }
+        );
+        assert_eq!(summary.messages[1].level, ConsoleLevel::Warning);
+        assert_eq!(summary.messages[1].source, None);
+    }
+}
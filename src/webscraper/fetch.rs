@@ -0,0 +1,158 @@
+use reqwest::header::{HeaderMap, LAST_MODIFIED, LOCATION, SET_COOKIE};
+use reqwest::redirect::Policy;
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const MAX_REDIRECTS: usize = 10;
+
+/// One response in a redirect chain
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct RedirectHop {
+    pub url: String,
+    pub status: u16,
+}
+
+/// Plain HTTP requests for data the browser doesn't expose, like response headers.
+pub fn http_client() -> Client {
+    Client::builder()
+        .user_agent(concat!("web_audit/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .expect("could not build http client")
+}
+
+/// Client that hands back 3xx responses instead of following them
+pub fn no_redirect_client() -> Client {
+    Client::builder()
+        .user_agent(concat!("web_audit/", env!("CARGO_PKG_VERSION")))
+        .redirect(Policy::none())
+        .build()
+        .expect("could not build http client")
+}
+
+/// Requests `url` and follows Location headers one hop at a time.
+/// The last hop is the final response (or the last redirect if the limit was hit).
+pub async fn redirect_chain(client: &Client, url: &str) -> Result<Vec<RedirectHop>, reqwest::Error> {
+    let mut chain = Vec::new();
+    let mut current = url.to_string();
+
+    loop {
+        let response = client.get(&current).send().await?;
+        let status = response.status();
+        chain.push(RedirectHop {
+            url: current.clone(),
+            status: status.as_u16(),
+        });
+
+        if !status.is_redirection() || chain.len() > MAX_REDIRECTS {
+            return Ok(chain);
+        }
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .and_then(|location| response.url().join(location).ok());
+        match location {
+            Some(next) => current = next.to_string(),
+            None => return Ok(chain),
+        }
+    }
+}
+
+/// A response's headers by lowercase name. Repeated headers are joined with `, `, as the HTTP
+/// spec allows. Set-Cookie is left out: it can't be joined and it carries session cookies that
+/// don't belong in the crawl results.
+pub fn header_map(response_headers: &HeaderMap) -> BTreeMap<String, String> {
+    let mut headers: BTreeMap<String, String> = BTreeMap::new();
+    for (name, value) in response_headers {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        if name == SET_COOKIE {
+            continue;
+        }
+        headers
+            .entry(name.as_str().to_string())
+            .and_modify(|joined| {
+                joined.push_str(", ");
+                joined.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+    headers
+}
+
+/// Resolves a possibly relative url against `base`
+pub fn resolve_url(base: &str, url: &str) -> Option<String> {
+    Url::parse(base).ok()?.join(url).ok().map(String::from)
+}
+
+/// Sends a HEAD request and returns the Last-Modified header as a W3C date (YYYY-MM-DD)
+pub async fn last_modified(client: &Client, url: &str) -> Option<String> {
+    let response = client.head(url).send().await.ok()?;
+    let header = response.headers().get(LAST_MODIFIED)?.to_str().ok()?;
+    http_date_to_w3c(header)
+}
+
+/// Converts an HTTP date like `Wed, 21 Oct 2015 07:28:00 GMT` into `2015-10-21`
+pub fn http_date_to_w3c(http_date: &str) -> Option<String> {
+    let mut parts = http_date.split_whitespace().skip(1);
+    let day: u8 = parts.next()?.parse().ok()?;
+    let month = match parts.next()? {
+        "Jan" => 1,
+        "Feb" => 2,
+        "Mar" => 3,
+        "Apr" => 4,
+        "May" => 5,
+        "Jun" => 6,
+        "Jul" => 7,
+        "Aug" => 8,
+        "Sep" => 9,
+        "Oct" => 10,
+        "Nov" => 11,
+        "Dec" => 12,
+        _ => return None,
+    };
+    let year: u16 = parts.next()?.parse().ok()?;
+    Some(format!("{:04}-{:02}-{:02}", year, month, day))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn http_date_to_w3c_test() {
+        assert_eq!(
+            http_date_to_w3c("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some("2015-10-21".to_string())
+        );
+        assert_eq!(http_date_to_w3c("Mon, 3 Jan 2022 00:00:00 GMT"), Some("2022-01-03".to_string()));
+        assert_eq!(http_date_to_w3c("yesterday"), None);
+    }
+
+    #[test]
+    fn resolve_url_test() {
+        assert_eq!(
+            resolve_url("https://example.com/old/page", "/new"),
+            Some("https://example.com/new".to_string())
+        );
+        assert_eq!(
+            resolve_url("https://example.com/old", "https://other.com/"),
+            Some("https://other.com/".to_string())
+        );
+    }
+
+    #[test]
+    fn header_map_test() {
+        let mut response_headers = HeaderMap::new();
+        response_headers.append("Cache-Control", "no-cache".parse().unwrap());
+        response_headers.append("Cache-Control", "no-store".parse().unwrap());
+        response_headers.append(SET_COOKIE, "session=secret; HttpOnly".parse().unwrap());
+
+        assert_eq!(
+            header_map(&response_headers),
+            BTreeMap::from([("cache-control".to_string(), "no-cache, no-store".to_string())])
+        );
+    }
+}
@@ -1,10 +1,28 @@
-use crate::webdriver::webdriver::{DriverHandle, WebDriver};
-use async_recursion::async_recursion;
+use crate::audit::a11y::a11y_rollup;
+use crate::audit::canonical::{analyze_canonicals, CANONICAL_CHAIN_RULE, HREFLANG_RULE};
+use crate::audit::lighthouse::LighthouseResult;
+use crate::audit::performance::{collect_page_timings, install_performance_observers, performance_summary, PageTimings};
+use crate::audit::rule::{Finding, RuleSet};
+use crate::audit::security::{header_matrix, SECURITY_HEADERS_RULE};
+use crate::audit::seo::{analyze_seo, SeoOptions, SEO_RULE};
+use crate::audit::weight::{collect_page_resources, resource_inventory, PageResources};
+use crate::config::Config;
+use crate::crawler::crawler::{Crawler, Detectors, Fetcher};
+use crate::crawler::events::CrawlEvent;
+use crate::linkgraph::analytics::{analyze_links, LinkMetrics};
+use crate::report::{update_report, Report};
+use crate::webdriver::webdriver::{WebDriver, WebDriverError};
+use crate::webscraper::console::{collect_console_messages, console_summary, install_console_listeners, ConsoleMessage};
+use crate::webscraper::fetch::RedirectHop;
+use crate::webscraper::page_load::{wait_for_page, PageLoadOptions};
+use crate::webscraper::resource::{probe_resource, ResourceInfo, ResourceKind};
 use fantoccini::elements::Element;
 use fantoccini::error::{CmdError, NewSessionError};
-use fantoccini::{Client, ClientBuilder, Locator};
+use fantoccini::{Client, Locator};
+use futures::StreamExt;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 use std::fs;
 use std::io::Write;
 use std::path::Path;
@@ -15,6 +33,28 @@ pub enum WebScrapingError {
     FantocciniCmdErrorr(CmdError),
     FormattingUrlError,
     WritingToFileError,
+    ReadingFromFileError,
+    WebDriverError(WebDriverError),
+    ConfigError(String),
+    ScriptError(String),
+    /// The receiver of a crawl's events was dropped
+    CrawlCancelled,
+}
+
+impl fmt::Display for WebScrapingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::FantocciniNewSessionError(e) => write!(f, "could not open webdriver session: {}", e),
+            Self::FantocciniCmdErrorr(e) => write!(f, "webdriver command failed: {}", e),
+            Self::FormattingUrlError => write!(f, "could not format url"),
+            Self::WritingToFileError => write!(f, "could not write to file"),
+            Self::ReadingFromFileError => write!(f, "could not read from file"),
+            Self::WebDriverError(e) => write!(f, "{}", e),
+            Self::ConfigError(reason) => write!(f, "invalid config: {}", reason),
+            Self::ScriptError(reason) => write!(f, "rule script failed: {}", reason),
+            Self::CrawlCancelled => write!(f, "crawl cancelled, nobody is listening to its events"),
+        }
+    }
 }
 
 impl From<CmdError> for WebScrapingError {
@@ -23,29 +63,128 @@ impl From<CmdError> for WebScrapingError {
     }
 }
 
+impl From<WebDriverError> for WebScrapingError {
+    fn from(e: WebDriverError) -> Self {
+        Self::WebDriverError(e)
+    }
+}
+
 impl From<NewSessionError> for WebScrapingError {
     fn from(e: NewSessionError) -> Self {
         Self::FantocciniNewSessionError(e)
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
 pub struct Url {
-    response_code: Option<u16>,
-    full_path: String,
-    site_references: Vec<String>,
-    redirected_to: Option<String>,
+    pub response_code: Option<u16>,
+    pub full_path: String,
+    pub site_references: Vec<String>,
+    pub redirected_to: Option<String>,
+    /// Every response from the first request to the final page, when known
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub redirect_chain: Vec<RedirectHop>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Absolute href of `<link rel="canonical">`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canonical: Option<String>,
+    /// Content of `<meta name="robots">`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub robots: Option<String>,
+    /// Content of `<meta name="description">`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Text of every `<h1>`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub h1s: Vec<String>,
+    /// Absolute href of every `<link rel="alternate" hreflang>` by hreflang value
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hreflang: BTreeMap<String, String>,
+    /// `og:*` meta properties by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub open_graph: BTreeMap<String, String>,
+    /// `twitter:*` meta tags by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub twitter_card: BTreeMap<String, String>,
+    /// Absolute src of every `<img>` on the page
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_metrics: Option<LinkMetrics>,
+    /// Set once the url was classified; anything but `Html` is never opened in the browser
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource_kind: Option<ResourceKind>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// Size in bytes as reported by the Content-Length header
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_length: Option<u64>,
+    /// Response headers by lowercase name, fetched when a rule looks at them
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// What the audit rules found on this page
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub findings: Vec<Finding>,
+    /// Console errors, warnings and uncaught exceptions seen after the page loaded
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub console: Vec<ConsoleMessage>,
+    /// Navigation timing and Core Web Vitals of the page load
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub performance: Option<PageTimings>,
+    /// Requests and bytes by resource type, from the Resource Timing API
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<PageResources>,
+    /// Scores and failed audits from `audit lighthouse`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lighthouse: Option<LighthouseResult>,
+}
+
+/// Collects the head metadata and images in one round trip to the browser
+const PAGE_METADATA_SCRIPT: &str = r#"
+    const canonical = document.querySelector('link[rel="canonical"]');
+    const robots = document.querySelector('meta[name="robots"]');
+    const description = document.querySelector('meta[name="description"]');
+    const metaTags = prefix => Object.fromEntries(
+        Array.from(document.querySelectorAll(`meta[property^="${prefix}"], meta[name^="${prefix}"]`))
+            .map(meta => [meta.getAttribute('property') || meta.getAttribute('name'), meta.getAttribute('content') || ''])
+    );
+    return {
+        title: document.title,
+        canonical: canonical ? canonical.href : null,
+        robots: robots ? robots.getAttribute('content') : null,
+        description: description ? description.getAttribute('content') || '' : null,
+        h1s: Array.from(document.querySelectorAll('h1')).map(h1 => h1.textContent.trim()),
+        hreflang: Object.fromEntries(
+            Array.from(document.querySelectorAll('link[rel="alternate"][hreflang]')).map(link => [link.hreflang, link.href])
+        ),
+        open_graph: metaTags('og:'),
+        twitter_card: metaTags('twitter:'),
+        images: Array.from(document.images).map(img => img.currentSrc || img.src).filter(src => src.startsWith('http')),
+    };
+"#;
+
+#[derive(Deserialize)]
+struct PageMetadata {
+    title: Option<String>,
+    canonical: Option<String>,
+    robots: Option<String>,
+    description: Option<String>,
+    h1s: Vec<String>,
+    hreflang: BTreeMap<String, String>,
+    open_graph: BTreeMap<String, String>,
+    twitter_card: BTreeMap<String, String>,
+    images: Vec<String>,
 }
 
 impl Url {
-    fn new(url: String, response_code: Option<u16>, site_reference: String) -> Url {
-        let mut new_vec = Vec::new();
-        new_vec.push(site_reference);
+    pub fn new(url: String, response_code: Option<u16>, site_reference: String) -> Url {
         Url {
             full_path: url,
-            response_code: response_code,
-            site_references: new_vec,
+            response_code,
+            site_references: vec![site_reference],
             redirected_to: None,
+            ..Default::default()
         }
     }
 
@@ -59,7 +198,68 @@ impl Url {
         self
     }
 
-    async fn set_response_code(&mut self, web_client: &mut Client, not_found_title: &String) -> Result<(), WebScrapingError> {
+    /// Records the responses of a redirect chain. The response code becomes the first
+    /// hop's status and the final hop is the redirect destination.
+    pub fn set_redirect_chain(&mut self, chain: Vec<RedirectHop>) -> &Self {
+        if let Some(first) = chain.first() {
+            self.response_code = Some(first.status);
+        }
+        if chain.len() > 1 {
+            if let Some(last) = chain.last() {
+                self.set_redirection(last.url.clone());
+            }
+        }
+        self.redirect_chain = chain;
+        self
+    }
+
+    /// Records what a HEAD request told about a resource that isn't a web page
+    pub fn set_resource_info(&mut self, kind: ResourceKind, info: ResourceInfo) -> &Self {
+        self.response_code = Some(info.status);
+        if info.final_url != self.full_path {
+            self.set_redirection(info.final_url);
+        }
+        self.resource_kind = Some(kind);
+        self.content_type = info.content_type;
+        self.content_length = info.content_length;
+        self
+    }
+
+    async fn set_page_metadata(&mut self, web_client: &mut Client) -> Result<(), WebScrapingError> {
+        let value = web_client.execute(PAGE_METADATA_SCRIPT, Vec::new()).await?;
+        if let Ok(metadata) = serde_json::from_value::<PageMetadata>(value) {
+            self.title = metadata.title;
+            self.canonical = metadata.canonical;
+            self.robots = metadata.robots;
+            self.description = metadata.description;
+            self.h1s = metadata.h1s;
+            self.hreflang = metadata.hreflang;
+            self.open_graph = metadata.open_graph;
+            self.twitter_card = metadata.twitter_card;
+            let mut seen = HashSet::new();
+            self.images = metadata.images;
+            self.images.retain(|image| seen.insert(image.clone()));
+        }
+        Ok(())
+    }
+
+    /// Whether search engines may index this page according to its robots meta tag
+    pub fn is_indexable(&self) -> bool {
+        match &self.robots {
+            Some(robots) => !robots.to_lowercase().contains("noindex"),
+            None => true,
+        }
+    }
+
+    /// A page is canonical when it has no canonical link or the link points back at itself
+    pub fn is_canonical(&self) -> bool {
+        match &self.canonical {
+            Some(canonical) => canonical.trim_end_matches('/') == self.full_path.trim_end_matches('/'),
+            None => true,
+        }
+    }
+
+    async fn set_response_code(&mut self, web_client: &mut Client, not_found_title: &str) -> Result<(), WebScrapingError> {
         let current_url = web_client.current_url().await?;
 
         if is_404(web_client, not_found_title).await? {
@@ -77,55 +277,94 @@ impl Url {
     }
 }
 
-/// Public function
+/// Crawls the site and writes the result to data/all_urls.json, with link analytics in the report
 pub async fn index_urls(
     starting_url: String,
     domains: Vec<String>,
-    not_found_title: String
+    not_found_title: String,
+    known_urls: Vec<String>,
+    driver: WebDriver,
+    config: Config,
 ) -> Result<(), WebScrapingError> {
-    //Launches WebDriver
-    let mut webdriver: DriverHandle = DriverHandle::new(WebDriver::GeckoDriver);
-
-    let first_url = Url::new(starting_url.clone(), None, starting_url.clone());
-
-    let url_hash_set: HashSet<String> = HashSet::from([starting_url.clone()]);
-
-    let url_index: HashMap<String, Url> = HashMap::from([(starting_url, first_url)]);
-
-    println!("Opening Up Web Client");
-    let mut web_client: Client = open_new_client().await?;
-    println!("Connected to Web Client");
-
-    let final_index: HashMap<String, Url> =
-        create_index(url_index, url_hash_set, domains, &mut web_client, &not_found_title).await?;
-
-    write_to_file(final_index)?;
-
-    println!("Closing to Web Client");
-    web_client.close().await?;
-    println!("Closed to Web Client");
-
-    //Exits Gecko-Driver
-    if let Err(e) = webdriver.kill() {
-        println!("Error closing Webdriver: {:?}", e);
+    let rules = RuleSet::from_config(&config)?;
+    let seo_rule = config.rule(SEO_RULE);
+    let seo_options: SeoOptions = seo_rule.options(SEO_RULE)?;
+    let check_hreflang = config.rule(HREFLANG_RULE).enabled;
+    let check_canonical_chains = config.rule(CANONICAL_CHAIN_RULE).enabled;
+    let check_a11y = config.a11y.enabled;
+    let check_security_headers = config.rule(SECURITY_HEADERS_RULE).enabled;
+    let summary_patterns = config.performance.summary_patterns.clone();
+    let crawler = Crawler::builder()
+        .seed(&starting_url)
+        .scope(domains)
+        .fetcher(Fetcher {
+            driver,
+            browser_options: config.browser,
+        })
+        .detectors(Detectors {
+            not_found_title,
+            rules,
+        })
+        .crawl_options(config.crawl)
+        .build()?;
+
+    let mut events = Box::pin(crawler.crawl());
+    while let Some(event) = events.next().await {
+        match event {
+            CrawlEvent::Finished(mut final_index) => {
+                let link_summary = analyze_links(&mut final_index, &starting_url, &known_urls);
+                let seo_summary = seo_rule.enabled.then(|| analyze_seo(&mut final_index, &seo_options));
+                let canonical_summary = analyze_canonicals(&mut final_index, check_hreflang, check_canonical_chains);
+                let a11y_summary = check_a11y.then(|| a11y_rollup(&final_index));
+                let console = console_summary(&final_index);
+                let performance = performance_summary(&final_index, &summary_patterns);
+                let resources = resource_inventory(&final_index);
+                let security_headers = check_security_headers.then(|| header_matrix(&final_index));
+
+                write_to_file(final_index, ALL_URLS_PATH)?;
+                update_report(|report: &mut Report| {
+                    report.link_analytics = Some(link_summary);
+                    report.seo = seo_summary;
+                    report.canonical = Some(canonical_summary);
+                    report.accessibility = a11y_summary;
+                    report.console = Some(console);
+                    report.performance = Some(performance);
+                    report.resources = Some(resources);
+                    report.security_headers = security_headers;
+                })?;
+            }
+            CrawlEvent::Error { url, error } => {
+                if let Some(url) = url {
+                    println!("Trouble visiting {}!", url);
+                }
+                return Err(error);
+            }
+            CrawlEvent::PageVisited(_) | CrawlEvent::LinkDiscovered { .. } => {}
+        }
     }
-
     Ok(())
 }
 
-/// Print to file data/all_urls.json
-fn write_to_file(hash_map: HashMap<String, Url>) -> Result<(), WebScrapingError> {
-    if let Err(_) = fs::DirBuilder::new().recursive(true).create("./data") {
-        println!("Trouble creating data directory!");
-        return Err(WebScrapingError::WritingToFileError);
+/// Where `index-urls` writes the crawl result and the other subcommands read it from by default
+pub const ALL_URLS_PATH: &str = "./data/all_urls.json";
+
+/// Print the crawl result to `path`, data/all_urls.json unless another input was given
+pub fn write_to_file(hash_map: HashMap<String, Url>, path: &str) -> Result<(), WebScrapingError> {
+    let directory = Path::new(path).parent().filter(|parent| !parent.as_os_str().is_empty());
+    if let Some(directory) = directory {
+        if fs::DirBuilder::new().recursive(true).create(directory).is_err() {
+            println!("Trouble creating data directory!");
+            return Err(WebScrapingError::WritingToFileError);
+        }
     }
     if let Ok(mut good_urls_file) = fs::File::options()
         .write(true)
         .create(true)
-        .open(Path::new("./data/all_urls.json"))
+        .truncate(true)
+        .open(Path::new(path))
     {
         if let Ok(string) = serde_json::to_string(&hash_map) {
-            if let Ok(_) = good_urls_file.write(string.as_bytes()) {
+            if good_urls_file.write_all(string.as_bytes()).is_ok() {
                 Ok(())
             } else {
                 println!("Trouble writing data!");
@@ -141,65 +380,69 @@ fn write_to_file(hash_map: HashMap<String, Url>) -> Result<(), WebScrapingError>
     }
 }
 
-#[async_recursion]
-async fn create_index(
-    mut url_index: HashMap<String, Url>,
-    mut url_list: HashSet<String>,
-    domains: Vec<String>,
-    web_client: &mut Client,
-    not_found_title: &String
-) -> Result<HashMap<String, Url>, WebScrapingError> {
-    let found_urls: HashSet<String> = url_list.clone();
-    let mut should_return = true;
-
-    let mut url_list_iter = found_urls.into_iter();
-    println!("Looping through {:?} urls ", url_list_iter.size_hint());
-    while let Some(url) = url_list_iter.next() {
-        //All urls we iterator through should be found in the index
-        if let Some(url_object) = url_index.get(&url) {
-            // println!("{:?}", url_object);
-            //If Url contains Some response code we know we already visited this url
-            if let Some(_) = url_object.response_code {
-                continue;
-            } else {
-                should_return = false;
-            }
+/// Reads a crawl result previously written by `write_to_file`
+pub fn read_from_file(path: &str) -> Result<HashMap<String, Url>, WebScrapingError> {
+    if let Ok(contents) = fs::read_to_string(Path::new(path)) {
+        if let Ok(hash_map) = serde_json::from_str(&contents) {
+            Ok(hash_map)
+        } else {
+            println!("Trouble Parsing data!");
+            Err(WebScrapingError::ReadingFromFileError)
         }
+    } else {
+        println!("Trouble Opening File!");
+        Err(WebScrapingError::ReadingFromFileError)
+    }
+}
 
-        let found_urls_vec: Vec<String> =
-            find_all_urls_from_webpage(&url, web_client, domains.clone(), &mut url_index, &not_found_title).await?;
-        //iterate through all urls and insert to HashSet.
-        let mut found_urls_iter = found_urls_vec.into_iter();
-        while let Some(found_url) = found_urls_iter.next() {
-            url_list.insert(found_url);
-        }
+/// Classifies `url_object` by its extension, or a HEAD request when the extension doesn't tell,
+/// and records status, type and size of anything that isn't a web page.
+/// Returns false for web pages, which still have to be visited in the browser. Their headers are
+/// kept when `keep_headers`, so the rules needing them don't request the page again.
+pub(crate) async fn check_resource(
+    http: &reqwest::Client,
+    url_object: &mut Url,
+    extension_only: bool,
+    keep_headers: bool,
+) -> bool {
+    let extension_kind = ResourceKind::from_extension(&url_object.full_path);
+    if extension_kind == Some(ResourceKind::Html) || (extension_kind.is_none() && extension_only) {
+        return false;
     }
 
-    if should_return {
-        return Ok(url_index);
-    } else {
-        return create_index(url_index, url_list, domains, web_client, &not_found_title).await;
+    match probe_resource(http, &url_object.full_path).await {
+        Ok(info) => match info.kind().or(extension_kind) {
+            Some(ResourceKind::Html) | None => {
+                if keep_headers {
+                    url_object.headers = info.headers;
+                }
+                false
+            }
+            Some(kind) => {
+                println!("Response {} from {:?}: {}", info.status, kind, url_object.full_path);
+                url_object.set_resource_info(kind, info);
+                true
+            }
+        },
+        Err(e) => match extension_kind {
+            Some(kind) => {
+                println!("Trouble checking {}: {}", url_object.full_path, e);
+                url_object.resource_kind = Some(kind);
+                true
+            }
+            None => false,
+        },
     }
 }
 
-async fn is_404(web_client: &mut Client, not_found_title: &String) -> Result<bool, WebScrapingError> {
+async fn is_404(web_client: &mut Client, not_found_title: &str) -> Result<bool, WebScrapingError> {
     let locator = Locator::XPath("//title");
 
     let mut title = web_client.find(locator).await?; //Element
 
     let title_text = title.html(true).await?;
 
-    if title_text.to_lowercase().contains(&not_found_title.to_lowercase()) {
-        return Ok(true);
-    } else {
-        return Ok(false);
-    }
-}
-
-async fn open_new_client() -> Result<Client, WebScrapingError> {
-    Ok(ClientBuilder::native()
-        .connect("http://localhost:4444")
-        .await?)
+    Ok(title_text.to_lowercase().contains(&not_found_title.to_lowercase()))
 }
 
 async fn find_urls(web_client: &mut Client) -> Result<Vec<String>, WebScrapingError> {
@@ -207,19 +450,14 @@ async fn find_urls(web_client: &mut Client) -> Result<Vec<String>, WebScrapingEr
 
     let all_anchors = web_client.find_all(locator).await?; //Vec<Elements>
 
-    let mut all_anchors_iter = all_anchors.iter();
-
     let mut all_urls: Vec<String> = Vec::new();
 
-    loop {
-        if let Some(element) = all_anchors_iter.next() {
-            if let Some(url) = get_href(element.clone()).await? {
-                all_urls.push(url);
-            };
-        } else {
-            return Ok(all_urls);
-        }
+    for element in all_anchors.iter() {
+        if let Some(url) = get_href(element.clone()).await? {
+            all_urls.push(url);
+        };
     }
+    Ok(all_urls)
 }
 
 async fn get_href(mut element: Element) -> Result<Option<String>, WebScrapingError> {
@@ -227,16 +465,14 @@ async fn get_href(mut element: Element) -> Result<Option<String>, WebScrapingErr
 }
 
 fn format_urls(mut domain: String, mut urls: Vec<String>) -> Vec<String> {
-    let mut urls_iter = urls.iter_mut();
-
     //remove '/' from end of domain if needed:
-    while domain.ends_with("/") {
+    while domain.ends_with('/') {
         domain.pop();
     }
 
-    while let Some(url) = urls_iter.next() {
+    for url in urls.iter_mut() {
         // Remove # to the end ->
-        if let Some(idx) = url.find("#") {
+        if let Some(idx) = url.find('#') {
             let (url_replacement, _) = url.split_at(idx);
 
             *url = url_replacement.to_string();
@@ -247,15 +483,13 @@ fn format_urls(mut domain: String, mut urls: Vec<String>) -> Vec<String> {
             //Adds https && http if not included
             let https = String::from("https://");
             let http = String::from("http://");
-            if !url.starts_with(&(https.clone() + &domain)) && !url.starts_with(&(http + &domain)) {
-                (*url).insert_str(0, &(https + &domain));
-            }
-        } else {
-            if !url.starts_with(&domain) {
-                //add domain to url
-                (*url).insert_str(0, &domain);
-                println!("New Url: {}", &url);
+            if !url.starts_with(&(https.clone() + domain.as_str())) && !url.starts_with(&(http + domain.as_str())) {
+                (*url).insert_str(0, &(https + domain.as_str()));
             }
+        } else if !url.starts_with(&domain) {
+            //add domain to url
+            (*url).insert_str(0, &domain);
+            println!("New Url: {}", &url);
         }
     }
     urls
@@ -272,18 +506,12 @@ fn add_to_list(
 
     urls = format_urls(current_domain, urls);
 
-    let mut url_iter = urls.iter();
-
-    while let Some(url_string) = url_iter.next() {
-        if !hash_map.contains_key(&url_string.to_string()) {
+    for url_string in urls.iter() {
+        if let Some(url_object) = hash_map.get_mut(url_string) {
+            (*url_object).add_reference(host.clone());
+        } else {
             let url_object = Url::new(url_string.to_string(), None, host.clone());
             hash_map.insert(url_string.to_string(), url_object);
-        } else {
-            if let Some(url_object) = hash_map.get_mut(&url_string.to_string()) {
-                (*url_object).add_reference(host.clone());
-            } else {
-                panic!("Could not find Url Key");
-            }
         }
     }
 
@@ -310,13 +538,10 @@ fn filter_domains(urls: Vec<String>, domain_list: Vec<String>) -> Vec<String> {
                 //Adds https && http if not included
                 let https = String::from("https://");
                 let http = String::from("http://");
-                if url.starts_with(&(https + domain)) {
-                    should_keep = true;
-                    break;
-                } else if url.starts_with(&(http + domain)) {
-                    should_keep = true;
-                    break;
-                } else if url.starts_with("/") {
+                if url.starts_with(&(https + domain.as_str()))
+                    || url.starts_with(&(http + domain.as_str()))
+                    || url.starts_with('/')
+                {
                     should_keep = true;
                     break;
                 }
@@ -326,24 +551,51 @@ fn filter_domains(urls: Vec<String>, domain_list: Vec<String>) -> Vec<String> {
         .collect()
 }
 
-async fn find_all_urls_from_webpage(
-    url_to_visit: &String,
+pub(crate) async fn find_all_urls_from_webpage(
+    url_to_visit: &str,
     web_client: &mut Client,
     domain_list: Vec<String>,
     hash_map: &mut HashMap<String, Url>,
-    not_found_title: &String
+    detectors: &Detectors,
+    http: &reqwest::Client,
+    page_load: &PageLoadOptions,
 ) -> Result<Vec<String>, WebScrapingError> {
-    web_client.goto(url_to_visit).await?;
+    match web_client.goto(url_to_visit).await {
+        // A page that never finishes loading is scraped as far as it rendered
+        Err(CmdError::Standard(e)) if e.error() == "timeout" => {
+            println!("Page load timed out for {}, scraping what has rendered", url_to_visit);
+        }
+        result => result?,
+    }
+    install_console_listeners(web_client).await?;
+    install_performance_observers(web_client).await?;
+    wait_for_page(web_client, page_load.wait_strategy_for(url_to_visit)).await;
 
     //set response code on url object:
     if let Some(url_object) = hash_map.get_mut(url_to_visit) {
-        (*url_object).set_response_code(web_client, not_found_title).await?;
+        (*url_object).set_response_code(web_client, &detectors.not_found_title).await?;
+        (*url_object).set_page_metadata(web_client).await?;
+        url_object.resource_kind = Some(ResourceKind::Html);
+        url_object.performance = collect_page_timings(web_client).await?;
+        url_object.resources = collect_page_resources(web_client, url_to_visit).await?;
+        // Urls classified over HTTP already brought their headers along
+        if detectors.rules.needs_headers() && url_object.headers.is_empty() {
+            match probe_resource(http, url_to_visit).await {
+                Ok(info) => url_object.headers = info.headers,
+                Err(e) => println!("Trouble fetching headers of {}: {}", url_to_visit, e),
+            }
+        }
     } else {
         panic!("Could not find Url Key");
     }
 
     let all_urls = find_urls(web_client).await?;
 
+    if let Some(url_object) = hash_map.get_mut(url_to_visit) {
+        url_object.findings = detectors.rules.check(url_object, &all_urls, web_client).await?;
+        url_object.console = collect_console_messages(web_client).await?;
+    }
+
     let current_url = web_client.current_url().await?;
     if let Some(current_domain) = current_url.domain() {
         if let Ok(formatted_urls) = add_to_list(
@@ -379,7 +631,8 @@ mod tests {
                 full_path: "https://example.com".to_string(),
                 response_code: None,
                 site_references: vec!["https://google.com/".to_string()],
-                redirected_to: None
+                redirected_to: None,
+                ..Default::default()
             }
         )
     }
@@ -502,11 +755,57 @@ mod tests {
                 full_path: String::from("https://example.com/base"),
                 response_code: Some(301),
                 site_references: vec!["https://example.com".to_string()],
-                redirected_to: Some(String::from("https://example.com/redirected"))
+                redirected_to: Some(String::from("https://example.com/redirected")),
+                ..Default::default()
             }
         )
     }
 
+    #[test]
+    fn url_set_redirect_chain_test() {
+        let mut url = Url::new(
+            "http://example.com/old".to_string(),
+            None,
+            "http://example.com".to_string(),
+        );
+        let chain = vec![
+            RedirectHop { url: "http://example.com/old".to_string(), status: 301 },
+            RedirectHop { url: "https://example.com/new".to_string(), status: 200 },
+        ];
+
+        url.set_redirect_chain(chain.clone());
+
+        assert_eq!(url.response_code, Some(301));
+        assert_eq!(url.redirected_to, Some("https://example.com/new".to_string()));
+        assert_eq!(url.redirect_chain, chain);
+    }
+
+    #[test]
+    fn url_set_resource_info_test() {
+        let mut url = Url::new(
+            "https://example.com/downloads/manual".to_string(),
+            None,
+            "https://example.com/downloads".to_string(),
+        );
+
+        url.set_resource_info(
+            ResourceKind::Pdf,
+            ResourceInfo {
+                status: 200,
+                final_url: "https://cdn.example.com/manual.pdf".to_string(),
+                content_type: Some("application/pdf".to_string()),
+                content_length: Some(48_213),
+                headers: BTreeMap::new(),
+            },
+        );
+
+        assert_eq!(url.response_code, Some(200));
+        assert_eq!(url.redirected_to, Some("https://cdn.example.com/manual.pdf".to_string()));
+        assert_eq!(url.resource_kind, Some(ResourceKind::Pdf));
+        assert_eq!(url.content_type, Some("application/pdf".to_string()));
+        assert_eq!(url.content_length, Some(48_213));
+    }
+
     #[test]
     fn url_add_reference_test() {
         let mut url = Url::new(
@@ -524,8 +823,35 @@ mod tests {
                 full_path: String::from("https://example.com/base"),
                 response_code: Some(301),
                 site_references: vec!["https://example.com".to_string(), destination.to_string()],
-                redirected_to: None
+                redirected_to: None,
+                ..Default::default()
             }
         )
     }
+
+    #[test]
+    fn url_serialize_test() {
+        let url = Url::new("https://example.com/".to_string(), Some(200), "https://example.com".to_string());
+        let json = serde_json::to_value(&url).unwrap();
+
+        for key in ["title", "canonical", "robots", "images"] {
+            assert!(json.get(key).is_none(), "{} is serialized", key);
+        }
+        assert_eq!(serde_json::from_value::<Url>(json).unwrap(), url);
+    }
+
+    #[test]
+    fn write_to_file_test() {
+        let path = std::env::temp_dir()
+            .join(format!("web_audit_write_{}", std::process::id()))
+            .join("other_site.json");
+        let path = path.to_str().unwrap();
+        let url = Url::new("https://example.com/".to_string(), Some(200), "https://example.com".to_string());
+        let index = HashMap::from([(url.full_path.clone(), url)]);
+
+        write_to_file(index.clone(), path).unwrap();
+
+        assert_eq!(read_from_file(path).unwrap(), index);
+        fs::remove_dir_all(Path::new(path).parent().unwrap()).unwrap();
+    }
 }
@@ -1 +1,6 @@
-pub mod find_urls;
+pub mod console;
+pub mod fetch;
+pub mod find_urls;
+pub mod page_load;
+pub mod resource;
+pub mod url_pattern;
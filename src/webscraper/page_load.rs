@@ -0,0 +1,210 @@
+use crate::webscraper::url_pattern::matches_pattern;
+use fantoccini::{Client, Locator};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// Resolves once no new resources were requested for `idleMs`, or after `timeoutMs`
+const NETWORK_IDLE_SCRIPT: &str = r#"
+    const [idleMs, timeoutMs, done] = arguments;
+    const start = performance.now();
+    let lastRequest = performance.now();
+    const observer = new PerformanceObserver(() => { lastRequest = performance.now(); });
+    observer.observe({ type: 'resource' });
+    const check = () => {
+        const now = performance.now();
+        const idle = now - lastRequest >= idleMs;
+        if (idle || now - start >= timeoutMs) {
+            observer.disconnect();
+            done(idle);
+        } else {
+            setTimeout(check, 50);
+        }
+    };
+    check();
+"#;
+
+/// WebDriver session timeouts, sent as the `timeouts` capability
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Timeouts {
+    pub page_load_ms: u64,
+    pub script_ms: u64,
+    pub implicit_ms: u64,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            page_load_ms: 30_000,
+            script_ms: 30_000,
+            implicit_ms: 0,
+        }
+    }
+}
+
+impl Timeouts {
+    pub fn to_capability(&self) -> Value {
+        json!({
+            "pageLoad": self.page_load_ms,
+            "script": self.script_ms,
+            "implicit": self.implicit_ms,
+        })
+    }
+}
+
+/// What to wait for after the browser fires the load event, before links are scraped
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum WaitStrategy {
+    /// Scrape as soon as navigation finished
+    #[default]
+    Load,
+    /// Wait until no new requests were made for `idle_ms`
+    NetworkIdle { idle_ms: u64, timeout_ms: u64 },
+    /// Wait until an element matching the CSS selector exists
+    Selector { selector: String, timeout_ms: u64 },
+    Delay { ms: u64 },
+}
+
+impl WaitStrategy {
+    /// Parses the --wait option: `load`, `network-idle`, `selector=<css>` or `delay=<ms>`
+    pub fn parse(value: &str) -> Option<WaitStrategy> {
+        match value.split_once('=') {
+            Some(("selector", selector)) => Some(WaitStrategy::Selector {
+                selector: selector.to_string(),
+                timeout_ms: 10_000,
+            }),
+            Some(("delay", ms)) => Some(WaitStrategy::Delay { ms: ms.parse().ok()? }),
+            None if value == "load" => Some(WaitStrategy::Load),
+            None if value == "network-idle" => Some(WaitStrategy::NetworkIdle {
+                idle_ms: 500,
+                timeout_ms: 10_000,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// A wait strategy for the urls matching `pattern`
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct PageLoadRule {
+    pub pattern: String,
+    pub wait: WaitStrategy,
+}
+
+/// The `[crawl.page_load]` section of the config file
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PageLoadOptions {
+    pub timeouts: Timeouts,
+    /// Used for every url that no rule matches
+    pub wait: WaitStrategy,
+    /// The first matching rule wins
+    pub rules: Vec<PageLoadRule>,
+}
+
+impl PageLoadOptions {
+    pub fn wait_strategy_for(&self, url: &str) -> &WaitStrategy {
+        self.rules
+            .iter()
+            .find(|rule| matches_pattern(&rule.pattern, url))
+            .map_or(&self.wait, |rule| &rule.wait)
+    }
+}
+
+/// Waits until the page is ready according to `strategy`. Timing out is not an error,
+/// the links that rendered so far are still scraped.
+pub async fn wait_for_page(web_client: &mut Client, strategy: &WaitStrategy) {
+    match strategy {
+        WaitStrategy::Load => {}
+        WaitStrategy::NetworkIdle { idle_ms, timeout_ms } => {
+            match web_client
+                .execute_async(NETWORK_IDLE_SCRIPT, vec![json!(idle_ms), json!(timeout_ms)])
+                .await
+            {
+                Ok(Value::Bool(true)) => {}
+                Ok(_) => println!("Network never went idle, continuing after {}ms", timeout_ms),
+                Err(e) => println!("Could not wait for network idle: {}", e),
+            }
+        }
+        WaitStrategy::Selector { selector, timeout_ms } => {
+            if let Err(e) = web_client
+                .wait()
+                .at_most(Duration::from_millis(*timeout_ms))
+                .for_element(Locator::Css(selector))
+                .await
+            {
+                println!("Gave up waiting for '{}': {}", selector, e);
+            }
+        }
+        WaitStrategy::Delay { ms } => tokio::time::sleep(Duration::from_millis(*ms)).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wait_strategy_parse_test() {
+        assert_eq!(WaitStrategy::parse("load"), Some(WaitStrategy::Load));
+        assert_eq!(
+            WaitStrategy::parse("selector=main a"),
+            Some(WaitStrategy::Selector {
+                selector: "main a".to_string(),
+                timeout_ms: 10_000
+            })
+        );
+        assert_eq!(WaitStrategy::parse("delay=250"), Some(WaitStrategy::Delay { ms: 250 }));
+        assert_eq!(WaitStrategy::parse("delay=soon"), None);
+        assert_eq!(WaitStrategy::parse("idle"), None);
+    }
+
+    #[test]
+    fn wait_strategy_for_test() {
+        let options = PageLoadOptions {
+            wait: WaitStrategy::Delay { ms: 100 },
+            rules: vec![PageLoadRule {
+                pattern: "/docs/*".to_string(),
+                wait: WaitStrategy::NetworkIdle {
+                    idle_ms: 500,
+                    timeout_ms: 5_000,
+                },
+            }],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            options.wait_strategy_for("https://example.com/docs/intro"),
+            &WaitStrategy::NetworkIdle {
+                idle_ms: 500,
+                timeout_ms: 5_000
+            }
+        );
+        assert_eq!(
+            options.wait_strategy_for("https://example.com/blog"),
+            &WaitStrategy::Delay { ms: 100 }
+        );
+    }
+
+    #[test]
+    fn page_load_options_from_toml_test() {
+        let options: PageLoadOptions = toml::from_str(
+            r##"
+            timeouts = { page_load_ms = 10000 }
+            wait = { strategy = "selector", selector = "#app a", timeout_ms = 5000 }
+
+            [[rules]]
+            pattern = "/static/*"
+            wait = { strategy = "load" }
+            "##,
+        )
+        .unwrap();
+
+        assert_eq!(options.timeouts.page_load_ms, 10_000);
+        assert_eq!(options.timeouts.script_ms, 30_000);
+        assert_eq!(options.rules[0].wait, WaitStrategy::Load);
+        assert_eq!(options.timeouts.to_capability()["pageLoad"], 10_000);
+    }
+}
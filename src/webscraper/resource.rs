@@ -0,0 +1,160 @@
+use reqwest::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use reqwest::{Client, StatusCode, Url as ParsedUrl};
+use crate::webscraper::fetch::header_map;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// What a url points at. Only `Html` pages are opened in the browser.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResourceKind {
+    Html,
+    Pdf,
+    Image,
+    Archive,
+    Document,
+    Media,
+    Other,
+}
+
+impl ResourceKind {
+    /// Guesses the kind from the file extension of the url path, `None` when there is no telling
+    pub fn from_extension(url: &str) -> Option<ResourceKind> {
+        let path = match ParsedUrl::parse(url) {
+            Ok(parsed) => parsed.path().to_string(),
+            Err(_) => url.split(['?', '#']).next().unwrap_or(url).to_string(),
+        };
+        let file_name = path.rsplit('/').next()?;
+        let (_, extension) = file_name.rsplit_once('.')?;
+        match extension.to_lowercase().as_str() {
+            "html" | "htm" | "xhtml" | "php" | "asp" | "aspx" | "jsp" => Some(ResourceKind::Html),
+            "pdf" => Some(ResourceKind::Pdf),
+            "png" | "jpg" | "jpeg" | "gif" | "webp" | "avif" | "svg" | "ico" | "bmp" | "tif" | "tiff" => {
+                Some(ResourceKind::Image)
+            }
+            "zip" | "gz" | "tgz" | "tar" | "rar" | "7z" | "bz2" | "xz" | "dmg" | "exe" | "msi" | "deb" | "rpm" => {
+                Some(ResourceKind::Archive)
+            }
+            "doc" | "docx" | "xls" | "xlsx" | "ppt" | "pptx" | "odt" | "ods" | "odp" | "csv" | "rtf" | "txt" => {
+                Some(ResourceKind::Document)
+            }
+            "mp3" | "mp4" | "m4a" | "wav" | "ogg" | "webm" | "mov" | "avi" | "mkv" | "flac" => {
+                Some(ResourceKind::Media)
+            }
+            "json" | "xml" | "js" | "css" | "woff" | "woff2" | "ttf" | "otf" | "eot" => Some(ResourceKind::Other),
+            _ => None,
+        }
+    }
+
+    /// Maps a Content-Type header value like `application/pdf; charset=binary` onto a kind
+    pub fn from_content_type(content_type: &str) -> ResourceKind {
+        let mime = content_type.split(';').next().unwrap_or("").trim().to_lowercase();
+        match mime.as_str() {
+            "text/html" | "application/xhtml+xml" => ResourceKind::Html,
+            "application/pdf" => ResourceKind::Pdf,
+            "application/zip"
+            | "application/gzip"
+            | "application/x-gzip"
+            | "application/x-tar"
+            | "application/x-7z-compressed"
+            | "application/x-rar-compressed"
+            | "application/vnd.rar"
+            | "application/x-bzip2"
+            | "application/octet-stream" => ResourceKind::Archive,
+            "application/msword" | "application/rtf" | "text/csv" | "text/plain" => ResourceKind::Document,
+            _ if mime.starts_with("image/") => ResourceKind::Image,
+            _ if mime.starts_with("audio/") || mime.starts_with("video/") => ResourceKind::Media,
+            _ if mime.starts_with("application/vnd.openxmlformats-officedocument")
+                || mime.starts_with("application/vnd.oasis.opendocument")
+                || mime.starts_with("application/vnd.ms-") =>
+            {
+                ResourceKind::Document
+            }
+            _ => ResourceKind::Other,
+        }
+    }
+}
+
+/// What a HEAD request tells about a url without downloading it
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ResourceInfo {
+    pub status: u16,
+    /// Where the request ended up after redirects
+    pub final_url: String,
+    pub content_type: Option<String>,
+    pub content_length: Option<u64>,
+    /// Every response header by lowercase name
+    pub headers: BTreeMap<String, String>,
+}
+
+impl ResourceInfo {
+    pub fn kind(&self) -> Option<ResourceKind> {
+        self.content_type.as_deref().map(ResourceKind::from_content_type)
+    }
+}
+
+/// Sends a HEAD request, falling back to a GET whose body is never read for servers that don't allow HEAD
+pub async fn probe_resource(client: &Client, url: &str) -> Result<ResourceInfo, reqwest::Error> {
+    let mut response = client.head(url).send().await?;
+    if matches!(
+        response.status(),
+        StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+    ) {
+        response = client.get(url).send().await?;
+    }
+
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value: &reqwest::header::HeaderValue| value.to_str().ok())
+            .map(String::from)
+    };
+    Ok(ResourceInfo {
+        status: response.status().as_u16(),
+        final_url: response.url().to_string(),
+        content_type: header(CONTENT_TYPE),
+        content_length: header(CONTENT_LENGTH).and_then(|length| length.parse().ok()),
+        headers: header_map(response.headers()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_extension_test() {
+        assert_eq!(
+            ResourceKind::from_extension("https://example.com/downloads/manual.PDF"),
+            Some(ResourceKind::Pdf)
+        );
+        assert_eq!(
+            ResourceKind::from_extension("https://example.com/release.tar.gz?v=2"),
+            Some(ResourceKind::Archive)
+        );
+        assert_eq!(
+            ResourceKind::from_extension("https://example.com/index.html"),
+            Some(ResourceKind::Html)
+        );
+        assert_eq!(ResourceKind::from_extension("https://example.com/about"), None);
+        assert_eq!(ResourceKind::from_extension("https://example.com/v1.2/"), None);
+    }
+
+    #[test]
+    fn from_content_type_test() {
+        assert_eq!(
+            ResourceKind::from_content_type("text/html; charset=utf-8"),
+            ResourceKind::Html
+        );
+        assert_eq!(ResourceKind::from_content_type("application/pdf"), ResourceKind::Pdf);
+        assert_eq!(ResourceKind::from_content_type("image/webp"), ResourceKind::Image);
+        assert_eq!(
+            ResourceKind::from_content_type(
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+            ),
+            ResourceKind::Document
+        );
+        assert_eq!(ResourceKind::from_content_type("application/json"), ResourceKind::Other);
+    }
+}
@@ -0,0 +1,63 @@
+use reqwest::Url as ParsedUrl;
+
+/// Matches a url against a glob like `/product/*` or `https://shop.example.com/*`.
+/// `*` matches any run of characters, including `/`. Patterns starting with `/` are
+/// compared with the path (and query) only, anything else with the full url.
+pub fn matches_pattern(pattern: &str, url: &str) -> bool {
+    if pattern.starts_with('/') {
+        let path = match ParsedUrl::parse(url) {
+            Ok(parsed) => match parsed.query() {
+                Some(query) => format!("{}?{}", parsed.path(), query),
+                None => parsed.path().to_string(),
+            },
+            Err(_) => url.to_string(),
+        };
+        glob_matches(pattern, &path)
+    } else {
+        glob_matches(pattern, url)
+    }
+}
+
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text index it was tried at, for backtracking
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_pattern_test() {
+        assert!(matches_pattern("/product/*", "https://example.com/product/blue-shirt"));
+        assert!(matches_pattern("/product/*", "https://example.com/product/"));
+        assert!(!matches_pattern("/product/*", "https://example.com/products"));
+        assert!(matches_pattern("/docs/*/api", "https://example.com/docs/v2/api"));
+        assert!(matches_pattern("*", "https://example.com/"));
+        assert!(matches_pattern("/search?*", "https://example.com/search?q=1"));
+        assert!(matches_pattern("https://shop.example.com/*", "https://shop.example.com/cart"));
+        assert!(!matches_pattern("https://shop.example.com/*", "https://example.com/cart"));
+        assert!(matches_pattern("/about", "https://example.com/about"));
+        assert!(!matches_pattern("/about", "https://example.com/about/team"));
+    }
+}